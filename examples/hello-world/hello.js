@@ -2,16 +2,13 @@ import fs from "node:fs";
 
 let mem = undefined;
 
-const toJsString = (offset) => {
-  const view = new DataView(mem.buffer);
-  const chars = [];
-  const len = view.getInt32(offset, true);
+const decoder = new TextDecoder();
 
-  for (let i = 0; i < len; i++) {
-    chars.push(view.getInt32(offset + (i + 1) * 4, true));
-  }
+// Wafer strings are a 4 byte length followed by that many bytes of UTF-8.
+const toJsString = (offset) => {
+  const len = new DataView(mem.buffer).getInt32(offset, true);
 
-  return String.fromCharCode(...chars);
+  return decoder.decode(new Uint8Array(mem.buffer, offset + 4, len));
 }
 
 const print = (offset) => { console.log(toJsString(offset)) };
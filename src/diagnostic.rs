@@ -0,0 +1,132 @@
+use std::fmt::Write;
+
+use crate::wafer::{CompileError, Source};
+
+const PRELUDE_PATH: &str = "prelude.wafer";
+const COLLECTOR_PATH: &str = "collector.wafer";
+
+struct Location<'a> {
+    line_start: usize,
+    line: usize,
+    column: usize,
+    text: &'a str,
+}
+
+fn locate(source: &str, offset: usize) -> Location<'_> {
+    let offset = offset.min(source.len());
+    let line_start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let line_end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |index| offset + index);
+
+    Location {
+        line_start,
+        line: source[..line_start].matches('\n').count() + 1,
+        column: source[line_start..offset].chars().count() + 1,
+        text: source[line_start..line_end].trim_end_matches('\r'),
+    }
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', "    ")
+}
+
+pub fn render(error: &CompileError, input_path: &str, input: &str) -> String {
+    let span = error.span();
+    let (path, source) = match span.source {
+        Source::Prelude => (PRELUDE_PATH, crate::PRELUDE),
+        Source::Collector => (COLLECTOR_PATH, crate::COLLECTOR),
+        Source::Input => (input_path, input),
+    };
+
+    let location = locate(source, span.start);
+    let line_end = location.line_start + location.text.len();
+    let prefix = &source[location.line_start..span.start];
+    let highlighted = &source[span.start..span.end.clamp(span.start, line_end)];
+
+    let gutter = " ".repeat(location.line.to_string().len());
+    let padding = expand_tabs(prefix).chars().map(|_| ' ').collect::<String>();
+    let carets = "^".repeat(expand_tabs(highlighted).chars().count().max(1));
+
+    let mut output = String::new();
+
+    writeln!(output, "error: {error}").unwrap();
+    writeln!(
+        output,
+        "{gutter}--> {path}:{}:{}",
+        location.line, location.column
+    )
+    .unwrap();
+    writeln!(output, "{gutter} |").unwrap();
+    writeln!(output, "{} | {}", location.line, expand_tabs(location.text)).unwrap();
+    writeln!(output, "{gutter} | {padding}{carets}").unwrap();
+
+    if let Some(help) = error.help() {
+        writeln!(output, "{gutter} |").unwrap();
+        writeln!(output, "{gutter} = help: {help}").unwrap();
+    }
+
+    output
+}
+
+pub fn summary(count: usize) -> String {
+    let plural = if count == 1 { "" } else { "s" };
+
+    format!("error: aborting due to {count} previous error{plural}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compile;
+
+    use super::{render, summary};
+
+    #[test]
+    fn should_render_error_with_source_snippet() {
+        let input = "public func main() {\n\tlet y = 1;\n\tx + y\n}\n";
+        let errors = compile(input).unwrap_err();
+
+        assert_eq!(
+            render(&errors[0], "main.wafer", input),
+            [
+                "error: cannot find variable `x` in this scope",
+                " --> main.wafer:3:2",
+                "  |",
+                "3 |     x + y",
+                "  |     ^",
+                "  |",
+                "  = help: declare it with `let x = ...;` before using it",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn should_underline_whole_span() {
+        let input = "public func main() { missing(1) }";
+        let errors = compile(input).unwrap_err();
+
+        let rendered = render(&errors[0], "main.wafer", input);
+
+        assert!(rendered.contains(" --> main.wafer:1:22\n"));
+        assert!(rendered.contains("\n  |                      ^^^^^^^\n"));
+    }
+
+    #[test]
+    fn should_render_syntax_errors_at_end_of_input() {
+        let input = "public func main() {";
+        let errors = compile(input).unwrap_err();
+
+        let rendered = render(&errors[0], "main.wafer", input);
+
+        assert!(rendered.starts_with("error: syntax error: expected"));
+        assert!(rendered.contains(" --> main.wafer:1:21\n"));
+    }
+
+    #[test]
+    fn should_summarise_error_count() {
+        assert_eq!(summary(1), "error: aborting due to 1 previous error\n");
+        assert_eq!(summary(3), "error: aborting due to 3 previous errors\n");
+    }
+}
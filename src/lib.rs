@@ -1,26 +1,51 @@
-use wafer::Wafer;
-use wasm::{Module, ValueType, WasmEncodable};
+use wafer::{Wafer, parse};
+use wasm::{Module, WasmEncodable};
 
+mod diagnostic;
 mod wafer;
 mod wasm;
 
+pub use diagnostic::{render, summary};
+pub use wafer::{CompileError, Options, Source, Span};
+
 const PRELUDE: &str = include_str!("prelude.wafer");
+const COLLECTOR: &str = include_str!("collector.wafer");
+
+pub fn compile(input: &str) -> Result<Vec<u8>, Vec<CompileError>> {
+    compile_with_options(input, Options::default())
+}
 
-pub fn compile(input: &str) -> Vec<u8> {
-    let input = format!("{PRELUDE}\n{input}");
-    let wafer = Wafer::parse(&input);
+pub fn compile_with_options(input: &str, options: Options) -> Result<Vec<u8>, Vec<CompileError>> {
+    let mut prelude = parse(PRELUDE, Source::Prelude).map_err(|error| vec![error])?;
+
+    if options.gc {
+        let collector = parse(COLLECTOR, Source::Collector).map_err(|error| vec![error])?;
+        prelude = prelude.layer(collector);
+    }
+
+    let input = parse(input, Source::Input).map_err(|error| vec![error])?;
+
+    let wafer = Wafer::from_ast(&prelude.merge(input), options)?;
     let mut module = Module::default();
 
     let num_imports = wafer.imports.len();
 
     for import in wafer.imports {
-        module.add_import(&import.name, import.parameters, vec![ValueType::I32]);
+        module.add_import(&import.name, import.parameters, import.returns);
+    }
+
+    for global in wafer.globals {
+        let index = module.add_global(global.r#type, global.mutable, global.init);
+
+        if global.public {
+            module.export_global(&global.name, index);
+        }
     }
 
     for function in wafer.functions {
         let index = module.add_function(
             function.parameters,
-            vec![ValueType::I32],
+            function.returns,
             function.locals,
             function.instructions,
         );
@@ -33,11 +58,9 @@ pub fn compile(input: &str) -> Vec<u8> {
     let index = module.add_memory(1, None);
     module.export_memory("$waferMemory", index);
 
-    let heap_base = wafer.data.len();
     module.add_data_segment(index, 0, wafer.data);
-    module.add_data_segment(index, heap_base, ((heap_base + 4) as i32).wasm_encode());
 
-    module.wasm_encode()
+    Ok(module.wasm_encode())
 }
 
 #[cfg(test)]
@@ -47,7 +70,7 @@ mod tests {
     use rstest::rstest;
     use wasmtime::{Engine, Instance, Linker, Module, Store};
 
-    use super::compile;
+    use super::{CompileError, Options, compile, compile_with_options};
 
     fn create_wasmi_instance(wasm: &[u8]) -> (Store<u32>, Instance) {
         let engine = Engine::default();
@@ -79,7 +102,7 @@ mod tests {
     #[case("let a = 13; let b = 15; a := 10; a + b", 25)]
     fn should_compile_simple_cases_correctly(#[case] input: &str, #[case] expected: i32) {
         let input = format!("public func main() {{ {input} }}");
-        let wasm = compile(&input);
+        let wasm = compile(&input).expect("couldn't compile");
         let (mut store, instance) = create_wasmi_instance(&wasm);
 
         let func = instance
@@ -90,6 +113,39 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[rstest]
+    #[case("1 + 2 * 4", 9)]
+    #[case("10 - 4 - 3", 3)]
+    #[case("100 / 10 / 5", 2)]
+    #[case("2 * 3 + 4 * 5", 26)]
+    #[case("10 - 2 * 3 + 1", 5)]
+    #[case("1 + 2 * 3 - 4 / 2", 5)]
+    #[case("7 - 6 % 4", 5)]
+    #[case("20 % 7 * 2", 12)]
+    #[case("2 * 3 % 4", 2)]
+    #[case("1 + 2 << 3", 24)]
+    #[case("1 << 2 + 1", 8)]
+    #[case("256 >> 2 >> 1", 32)]
+    #[case("-16 >>> 28 + 0", 15)]
+    #[case("2 + 3 > 4", 1)]
+    #[case("1 << 2 < 5", 1)]
+    #[case("1 < 2 == 3 > 4", 0)]
+    #[case("1 + 2 * 3 < 8 == 1", 1)]
+    #[case("1 == 1 & 0", 0)]
+    #[case("6 & 3 == 3", 0)]
+    #[case("1 | 2 ^ 3 & 4", 3)]
+    #[case("5 ^ 3 | 8", 14)]
+    #[case("6 ^ 3 & 5", 7)]
+    #[case("-2 * -3", 6)]
+    #[case("~0 + 2", 1)]
+    #[case("!0 + 1", 2)]
+    #[case("-(1 + 2) * 3", -9)]
+    fn should_follow_c_operator_precedence(#[case] input: &str, #[case] expected: i32) {
+        let (_, _, result) = run_main(input).expect("couldn't call function");
+
+        assert_eq!(result, expected);
+    }
+
     #[rstest]
     #[case("add", 579)]
     #[case("if", 36)]
@@ -101,9 +157,268 @@ mod tests {
     #[case("memory", 64)]
     #[case("array", 64)]
     #[case("strings", 21840)]
-    fn should_compile_fixtures_correctly(#[case] fixture_name: &str, #[case] expected: i32) {
+    #[case("i64", 347557)]
+    #[case("floats", 314)]
+    #[case("return", 564)]
+    #[case("void", 143)]
+    #[case("break", 56100)]
+    #[case("for", 1246)]
+    #[case("fib_for", 89)]
+    #[case("array_for", 64)]
+    #[case("scopes", 611)]
+    #[case("globals", 3686)]
+    #[case("alloc", 499500)]
+    #[case("grow", 4950)]
+    #[case("gc", 125200)]
+    #[case("bytes", 5817)]
+    #[case("utf8", 446)]
+    #[case("structs", 5196)]
+    #[case("literals", 335155)]
+    #[case("match", 895527)]
+    #[case("operators", 64511)]
+    #[case("logical", 8832)]
+    #[case("unsigned", 2047)]
+    fn should_compile_fixtures_correctly(
+        #[case] fixture_name: &str,
+        #[case] expected: i32,
+        #[values(0, 1, 2)] opt_level: u8,
+    ) {
         let input = read_to_string(format!("fixtures/{fixture_name}.wafer")).unwrap();
-        let wasm = compile(&input);
+        let options = Options {
+            opt_level,
+            ..Options::default()
+        };
+        let wasm = compile_with_options(&input, options).expect("couldn't compile");
+        let (mut store, instance) = create_wasmi_instance(&wasm);
+
+        let func = instance
+            .get_typed_func::<(), i32>(&mut store, "main")
+            .expect("couldn't find function");
+
+        let result = func.call(&mut store, ()).expect("couldn't call function");
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn should_export_void_functions() {
+        let input = read_to_string("fixtures/void.wafer").unwrap();
+        let wasm = compile(&input).expect("couldn't compile");
+        let (mut store, instance) = create_wasmi_instance(&wasm);
+
+        let func = instance
+            .get_typed_func::<(), ()>(&mut store, "run")
+            .expect("couldn't find function");
+
+        func.call(&mut store, ()).expect("couldn't call function");
+    }
+
+    #[test]
+    fn should_export_public_globals() {
+        let input = read_to_string("fixtures/globals.wafer").unwrap();
+        let wasm = compile(&input).expect("couldn't compile");
+        let (mut store, instance) = create_wasmi_instance(&wasm);
+
+        let func = instance
+            .get_typed_func::<(), i32>(&mut store, "main")
+            .expect("couldn't find function");
+        let total = instance
+            .get_global(&mut store, "total")
+            .expect("couldn't find global");
+
+        assert_eq!(total.get(&mut store).i64(), Some(0));
+        func.call(&mut store, ()).expect("couldn't call function");
+        assert_eq!(total.get(&mut store).i64(), Some(256));
+    }
+
+    #[test]
+    fn should_reuse_freed_memory() {
+        let input = r"
+            public func main() {
+                for round in 0..100 {
+                    let first = newInt32Array(20000);
+                    let second = alloc(1000);
+                    free(first);
+                    free(second);
+                }
+
+                let a = alloc(16);
+                let b = alloc(16);
+                free(a);
+                let c = alloc(8);
+                free(b);
+                free(c);
+                alloc(24) - a
+            }
+        ";
+        let wasm = compile(input).expect("couldn't compile");
+        let (mut store, instance) = create_wasmi_instance(&wasm);
+
+        let func = instance
+            .get_typed_func::<(), i32>(&mut store, "main")
+            .expect("couldn't find function");
+        let memory = instance
+            .get_memory(&mut store, "$waferMemory")
+            .expect("couldn't find memory");
+
+        let result = func.call(&mut store, ()).expect("couldn't call function");
+
+        assert_eq!(result, 0);
+        assert_eq!(memory.size(&store), 2);
+    }
+
+    #[test]
+    fn should_collect_unreachable_memory() {
+        let input = read_to_string("fixtures/gc.wafer").unwrap();
+        let wasm = compile_with_options(
+            &input,
+            Options {
+                gc: true,
+                ..Options::default()
+            },
+        )
+        .expect("couldn't compile");
+        let (mut store, instance) = create_wasmi_instance(&wasm);
+
+        let func = instance
+            .get_typed_func::<(), i32>(&mut store, "main")
+            .expect("couldn't find function");
+        let memory = instance
+            .get_memory(&mut store, "$waferMemory")
+            .expect("couldn't find memory");
+
+        let result = func.call(&mut store, ()).expect("couldn't call function");
+
+        assert_eq!(result, 125200);
+        assert!(memory.size(&store) < 4);
+    }
+
+    #[rstest]
+    // Deep enough for the frames to fill more than one shadow stack chunk.
+    #[case(
+        r"
+            func nest(n) {
+                let node = newInt32Array(64);
+                node[63] := n;
+                let deeper = if n > 0 { nest(n - 1) } else { 0 };
+                node[63] + deeper
+            }
+
+            public func main() { nest(4000) }
+        ",
+        8002000
+    )]
+    // Long enough to overflow the call stack if marking recursed.
+    #[case(
+        r"
+            public func main() {
+                let head = 0;
+
+                for i in 0..100000 {
+                    let node = newInt32Array(2);
+                    node[0] := i;
+                    node[1] := head;
+                    head := node;
+                }
+
+                let total = 0;
+
+                while head != 0 {
+                    total := total + head[0];
+                    head := head[1];
+                }
+
+                total
+            }
+        ",
+        704982704
+    )]
+    // The rows are allocated while the prelude holds the only pointer to
+    // the array of rows.
+    #[case(
+        r"
+            public func main() {
+                let grid = newArray2D(20000, 8);
+                grid[7][19999] := 5;
+
+                grid[0][0] + grid[7][19999] + len(grid) + len(grid[7])
+            }
+        ",
+        20013
+    )]
+    // Freed strings leave their old contents behind, which mustn't be taken
+    // for the start of a block.
+    #[case(
+        r#"
+            public func main() {
+                for i in 0..10 {
+                    let junk = newInt32Array(1000);
+                }
+
+                let s = "x";
+
+                for i in 0..3000 {
+                    s := strConcat("ab", intToStr(i));
+                }
+
+                strLen(s)
+            }
+        "#,
+        6
+    )]
+    // The first array is only held on the operand stack while the second is
+    // allocated.
+    #[case(
+        r"
+            func mk(v): Int32Array {
+                if v == 2 {
+                    __gcCollect();
+                }
+
+                let a = newInt32Array(10);
+                a[0] := v;
+                a
+            }
+
+            func f(a, b) { a[0] * 10 + b[0] }
+
+            public func main() { f(mk(1), mk(2)) }
+        ",
+        12
+    )]
+    // Constant array literals live in the data segment, but can still hold the
+    // only pointer to an array on the heap.
+    #[case(
+        r"
+            func table(): Int32Array { [0, 0] }
+
+            func put() {
+                let inner = newInt32Array(1);
+                inner[0] := 42;
+
+                let t = table();
+                t[0] := inner;
+            }
+
+            public func main() {
+                put();
+
+                for i in 0..2000 {
+                    let junk = newInt32Array(1000);
+                }
+
+                let t = table();
+                let inner = t[0];
+                inner[0]
+            }
+        ",
+        42
+    )]
+    fn should_keep_reachable_memory_with_gc(#[case] input: &str, #[case] expected: i32) {
+        let options = Options {
+            gc: true,
+            ..Options::default()
+        };
+        let wasm = compile_with_options(input, options).expect("couldn't compile");
         let (mut store, instance) = create_wasmi_instance(&wasm);
 
         let func = instance
@@ -111,13 +426,142 @@ mod tests {
             .expect("couldn't find function");
 
         let result = func.call(&mut store, ()).expect("couldn't call function");
+
+        assert_eq!(result, expected);
+    }
+
+    fn run_main(body: &str) -> Result<(Store<u32>, Instance, i32), wasmtime::Error> {
+        let input = format!("public func main() {{ {body} }}");
+        let wasm = compile(&input).expect("couldn't compile");
+        let (mut store, instance) = create_wasmi_instance(&wasm);
+
+        let func = instance
+            .get_typed_func::<(), i32>(&mut store, "main")
+            .expect("couldn't find function");
+
+        let result = func.call(&mut store, ())?;
+
+        Ok((store, instance, result))
+    }
+
+    #[rstest]
+    #[case(r#"strConcat("caf\u{e9} ", "\"\u{1F600}\"\n")"#, "café \"😀\"\n")]
+    #[case(r#"concat("a", "bc", "", "d")"#, "abcd")]
+    #[case(r#"concat("x")"#, "x")]
+    #[case(r#"strSlice("hello, world", 7, 12)"#, "world")]
+    #[case(r#"strSlice("hello", 2, 2)"#, "")]
+    #[case("intToStr(0)", "0")]
+    #[case("intToStr(12345)", "12345")]
+    #[case("intToStr(0 - 2147483647 - 1)", "-2147483648")]
+    #[case(r#"concat("n = ", intToStr(strToInt("-42") * 2))"#, "n = -84")]
+    fn should_build_strings_at_runtime(#[case] body: &str, #[case] expected: &str) {
+        let (mut store, instance, offset) = run_main(body).expect("couldn't call function");
+        let memory = instance
+            .get_memory(&mut store, "$waferMemory")
+            .expect("couldn't find memory");
+
+        let data = memory.data(&store);
+        let offset = offset as usize;
+        let len = i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+
+        assert_eq!(
+            str::from_utf8(&data[offset + 4..offset + 4 + len]),
+            Ok(expected)
+        );
+    }
+
+    #[rstest]
+    #[case(r#"strCompare("apple", "banana")"#, -1)]
+    #[case(r#"strCompare("apple", "apple")"#, 0)]
+    #[case(r#"strCompare("apples", "apple")"#, 1)]
+    #[case(r#"strCompare("é", "z")"#, 1)]
+    #[case(r#"strEq(concat("ab", "c"), "abc")"#, 1)]
+    #[case(r#"strLen("😀")"#, 4)]
+    #[case(r#"strToInt("12345")"#, 12345)]
+    #[case(r#"strToInt("+7")"#, 7)]
+    #[case(r#"strToInt("-2147483648")"#, i32::MIN)]
+    fn should_compare_and_parse_strings(#[case] body: &str, #[case] expected: i32) {
+        let (_, _, result) = run_main(body).expect("couldn't call function");
+
         assert_eq!(result, expected);
     }
 
+    #[rstest]
+    #[case(r#"strToInt("12a")"#)]
+    #[case(r#"strToInt("-")"#)]
+    #[case(r#"strSlice("abc", 2, 4)"#)]
+    #[case(r#"strByteAt("abc", 3)"#)]
+    fn should_trap_on_invalid_string_operations(#[case] body: &str) {
+        assert!(run_main(body).is_err());
+    }
+
+    #[rstest]
+    #[case("let grid = newArray2D(3, 2); grid[2][0]")]
+    #[case("let grid = newArray2D(3, 2); grid[1][3]")]
+    #[case("let grid = [[1], [2, 3]]; grid[0][1] := 4")]
+    #[case("let empty = []; empty[0]")]
+    #[case("let grid = newArray2D(3, 2); grid[-1][0]")]
+    fn should_check_bounds_of_nested_arrays(#[case] body: &str) {
+        assert!(run_main(body).is_err());
+    }
+
+    #[test]
+    fn should_share_constant_array_literals() {
+        let input = r"
+            func count() {
+                let a = [0];
+                a[0] := a[0] + 1;
+                a[0]
+            }
+
+            func fresh() {
+                let a = newInt32Array(1);
+                a[0] := a[0] + 1;
+                a[0]
+            }
+
+            public func main() {
+                count();
+                count();
+                fresh();
+                count() * 10 + fresh()
+            }
+        ";
+        let wasm = compile(input).expect("couldn't compile");
+        let (mut store, instance) = create_wasmi_instance(&wasm);
+
+        let func = instance
+            .get_typed_func::<(), i32>(&mut store, "main")
+            .expect("couldn't find function");
+
+        let result = func.call(&mut store, ()).expect("couldn't call function");
+        assert_eq!(result, 31);
+    }
+
+    #[test]
+    fn should_trap_on_unmatched_enum_values() {
+        let input = r"
+            enum E { A, B }
+
+            public func main() {
+                let e: E = 2;
+                match e { E::A => 1, E::B => 2 }
+            }
+        ";
+        let wasm = compile(input).expect("couldn't compile");
+        let (mut store, instance) = create_wasmi_instance(&wasm);
+
+        let func = instance
+            .get_typed_func::<(), i32>(&mut store, "main")
+            .expect("couldn't find function");
+
+        assert!(func.call(&mut store, ()).is_err());
+    }
+
     #[test]
     fn should_panic_on_out_of_bounds() {
         let input = read_to_string("fixtures/bounds.wafer").unwrap();
-        let wasm = compile(&input);
+        let wasm = compile(&input).expect("couldn't compile");
         let (mut store, instance) = create_wasmi_instance(&wasm);
 
         let func = instance
@@ -127,4 +571,178 @@ mod tests {
         let result = func.call(&mut store, ());
         assert!(result.is_err());
     }
+
+    #[rstest]
+    #[case(
+        "public func main() { 1 + }",
+        |error: &CompileError| matches!(error, CompileError::Syntax { .. })
+    )]
+    #[case(
+        "public func main() { x }",
+        |error: &CompileError| matches!(error, CompileError::UnknownVariable { .. })
+    )]
+    #[case(
+        "public func main() { missing() }",
+        |error: &CompileError| matches!(error, CompileError::UnknownFunction { .. })
+    )]
+    // The collector's runtime is only included with the garbage collector.
+    #[case(
+        "public func main() { __gcCollect(); 0 }",
+        |error: &CompileError| matches!(error, CompileError::UnknownFunction { .. })
+    )]
+    #[case(
+        "public func main() { newInt32Array(1, 2) }",
+        |error: &CompileError| matches!(error, CompileError::ArityMismatch { .. })
+    )]
+    #[case(
+        "public func main() { 0 } func main() { 1 }",
+        |error: &CompileError| matches!(error, CompileError::DuplicateDefinition { .. })
+    )]
+    #[case(
+        "public func main() { let a: i64 = 1; a }",
+        |error: &CompileError| matches!(error, CompileError::MismatchedTypes { .. })
+    )]
+    #[case(
+        "public func main() { 1.5 }",
+        |error: &CompileError| matches!(error, CompileError::MismatchedTypes { .. })
+    )]
+    #[case(
+        "public func main(): i32 { }",
+        |error: &CompileError| matches!(error, CompileError::MissingReturn { .. })
+    )]
+    #[case(
+        "public func main() { return 1; }",
+        |error: &CompileError| matches!(error, CompileError::UnexpectedValue { .. })
+    )]
+    #[case(
+        "public func main() { let a = b; let b = 1; a }",
+        |error: &CompileError| matches!(error, CompileError::UseBeforeDeclaration { .. })
+    )]
+    #[case(
+        "public func main() { if 1 { let a = 1; } a }",
+        |error: &CompileError| matches!(error, CompileError::OutOfScope { .. })
+    )]
+    #[case(
+        "const A = 1; public func main() { A := 2 }",
+        |error: &CompileError| matches!(error, CompileError::AssignToConstant { .. })
+    )]
+    #[case(
+        "let a = 1; let b = a; public func main() { b }",
+        |error: &CompileError| matches!(error, CompileError::NotConstant { .. })
+    )]
+    #[case(
+        "const A = 1 / 0; public func main() { A }",
+        |error: &CompileError| matches!(error, CompileError::DivisionByZero { .. })
+    )]
+    #[case(
+        "struct P { x } public func main() { let p = P { x: 1 }; p.y }",
+        |error: &CompileError| matches!(error, CompileError::UnknownField { .. })
+    )]
+    #[case(
+        "struct P { x, y } public func main() { let p = P { x: 1 }; p.x }",
+        |error: &CompileError| matches!(error, CompileError::MissingField { .. })
+    )]
+    #[case(
+        "public func main() { let p = Q { x: 1 }; 0 }",
+        |error: &CompileError| matches!(error, CompileError::UnknownType { .. })
+    )]
+    #[case(
+        "public func main() { concat() }",
+        |error: &CompileError| matches!(error, CompileError::ArityMismatch { .. })
+    )]
+    #[case(
+        "public func main() { concat(\"a\", 1.5) }",
+        |error: &CompileError| matches!(error, CompileError::MismatchedTypes { .. })
+    )]
+    #[case(
+        "public func main() { let a = [1, 2.5]; 0 }",
+        |error: &CompileError| matches!(error, CompileError::MismatchedTypes { .. })
+    )]
+    #[case(
+        "public func main() { len() }",
+        |error: &CompileError| matches!(error, CompileError::ArityMismatch { .. })
+    )]
+    #[case(
+        "public func main() { let a = 1.5; a[0] }",
+        |error: &CompileError| matches!(error, CompileError::MismatchedTypes { .. })
+    )]
+    #[case(
+        "enum E { A, B } public func main() { match E::A { E::A => 1 } }",
+        |error: &CompileError| matches!(error, CompileError::NonExhaustiveMatch { .. })
+    )]
+    #[case(
+        "public func main() { match 1 { 1 => 1, 1 => 5, _ => 0 } }",
+        |error: &CompileError| matches!(error, CompileError::UnreachablePattern { .. })
+    )]
+    #[case(
+        "enum E { A } public func main() { E::B }",
+        |error: &CompileError| matches!(error, CompileError::UnknownVariant { .. })
+    )]
+    #[case(
+        "enum E { A = 1, B = 1 } public func main() { 0 }",
+        |error: &CompileError| matches!(error, CompileError::DuplicateDiscriminant { .. })
+    )]
+    #[case(
+        "public func main() { match 1.5 { _ => 1 } }",
+        |error: &CompileError| matches!(error, CompileError::MismatchedTypes { .. })
+    )]
+    #[case(
+        "public func main() { 1.5 % 2.0 }",
+        |error: &CompileError| matches!(error, CompileError::MismatchedTypes { .. })
+    )]
+    #[case(
+        "public func main() { let f = 1.5; ~f }",
+        |error: &CompileError| matches!(error, CompileError::MismatchedTypes { .. })
+    )]
+    #[case(
+        "public func main() { !1.5 }",
+        |error: &CompileError| matches!(error, CompileError::MismatchedTypes { .. })
+    )]
+    #[case(
+        "public func main() { let a: u32 = 1; let b: i32 = a; b }",
+        |error: &CompileError| matches!(error, CompileError::MismatchedTypes { .. })
+    )]
+    #[case(
+        "public func main() { let a: u32 = 0x100000000; 0 }",
+        |error: &CompileError| matches!(error, CompileError::NumberOutOfRange { .. })
+    )]
+    #[case(
+        "public func main() { 0x10000000000000000 }",
+        |error: &CompileError| matches!(error, CompileError::NumberOutOfRange { .. })
+    )]
+    fn should_return_errors_for_invalid_programs(
+        #[case] input: &str,
+        #[case] expected: fn(&CompileError) -> bool,
+    ) {
+        let errors = compile(input).unwrap_err();
+
+        assert!(!errors.is_empty());
+        assert!(errors.iter().all(expected), "unexpected errors: {errors:?}");
+    }
+
+    #[test]
+    fn should_report_every_error() {
+        let input = r"
+            public func main() {
+                let a = b;
+                c(a) + add(a)
+            }
+
+            func add(x, y) { x + y }
+            func add(x) { x }
+        ";
+
+        let errors = compile(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "cannot find variable `b` in this scope",
+                "cannot find function `c`",
+                "function `add` takes 2 argument(s) but 1 were supplied",
+                "`add` is defined multiple times",
+            ]
+        );
+    }
 }
@@ -1,15 +1,79 @@
 use std::env::args;
 use std::fs;
 use std::path::PathBuf;
+use std::process::exit;
 
-use wasm_ground_up::compile;
+use wasm_ground_up::{Options, compile_with_options, render, summary};
+
+const USAGE: &str = "usage: wasm_ground_up [--gc] [-O<level>] <input path>";
 
 pub fn main() {
-    let input_path = args().nth(1).expect("usage: wasm_ground_up <input path>");
+    let (flags, paths): (Vec<_>, Vec<_>) = args().skip(1).partition(|arg| arg.starts_with('-'));
+    let (Some(options), Some(input_path)) = (options(&flags), paths.into_iter().next()) else {
+        eprintln!("{USAGE}");
+        exit(2);
+    };
     let input = fs::read_to_string(&input_path).expect("failed to read file");
 
-    let wasm = compile(&input);
+    let wasm = match compile_with_options(&input, options) {
+        Ok(wasm) => wasm,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", render(error, &input_path, &input));
+            }
+
+            eprint!("{}", summary(errors.len()));
+            exit(1);
+        }
+    };
 
     let output_path = PathBuf::from(&input_path).with_extension("wasm");
     fs::write(output_path, wasm).expect("failed to write WASM");
 }
+
+// Unknown flags and levels that aren't numbers give `None`. The last `-O` wins.
+fn options(flags: &[String]) -> Option<Options> {
+    let mut options = Options::default();
+
+    for flag in flags {
+        match flag.strip_prefix("-O") {
+            // `-O` alone means `-O1`, like in C compilers.
+            Some("") => options.opt_level = 1,
+            Some(level) => options.opt_level = level.parse().ok()?,
+            None if flag == "--gc" => options.gc = true,
+            None => return None,
+        }
+    }
+
+    Some(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::options;
+
+    fn parse(flags: &[&str]) -> Option<(bool, u8)> {
+        let flags: Vec<_> = flags.iter().map(|flag| flag.to_string()).collect();
+
+        options(&flags).map(|options| (options.gc, options.opt_level))
+    }
+
+    #[test]
+    fn should_parse_flags() {
+        assert_eq!(parse(&[]), Some((false, 0)));
+        assert_eq!(parse(&["-O"]), Some((false, 1)));
+        assert_eq!(parse(&["--gc", "-O2", "-O0"]), Some((true, 0)));
+    }
+
+    #[test]
+    fn should_reject_invalid_levels() {
+        assert_eq!(parse(&["-Ofoo"]), None);
+        assert_eq!(parse(&["-O-1"]), None);
+    }
+
+    #[test]
+    fn should_reject_unknown_flags() {
+        assert_eq!(parse(&["--help"]), None);
+        assert_eq!(parse(&["--gc", "-g"]), None);
+    }
+}
@@ -1,374 +1,1499 @@
-mod strings;
+mod ast;
+mod checker;
+mod constants;
+mod data;
+mod error;
+mod parser;
+mod shadow;
 mod symbols;
+mod types;
 
-use std::str::FromStr;
+use std::collections::HashSet;
 
-use pest::Parser as PestParser;
-use pest::iterators::Pair;
-use strings::Strings;
-use symbols::Symbols;
+use ast::{
+    Arm, BinaryOperator, Block, Expr, ExprKind, Identifier, Iterable, Module, Pattern, Stmt,
+    StmtKind, UnaryOperator,
+};
+use constants::{Constants, Value};
+use data::Data;
+use shadow::{MARK_GLOBALS, ShadowFrame};
+use symbols::{Symbols, Variable, hidden_name, temp_local};
+use types::{Element, Environment, Type, TypeNames};
 
 use crate::wasm::{Instruction, ValueType};
 
-#[derive(pest_derive::Parser)]
-#[grammar = "src/wafer.pest"]
-struct Parser;
+pub use error::{CompileError, Source, Span};
+pub use parser::parse;
 
 pub struct Import {
     pub name: String,
     pub parameters: Vec<ValueType>,
+    pub returns: Vec<ValueType>,
+}
+
+pub struct Global {
+    pub name: String,
+    pub public: bool,
+    pub mutable: bool,
+    pub r#type: ValueType,
+    pub init: Instruction,
 }
 
 pub struct Function {
     pub name: String,
     pub public: bool,
     pub parameters: Vec<ValueType>,
+    pub returns: Vec<ValueType>,
     pub locals: Vec<(usize, ValueType)>,
     pub instructions: Vec<Instruction>,
 }
 
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+    // Adds the garbage collector to the prelude, which needs user functions to
+    // maintain a shadow stack.
+    pub gc: bool,
+    // 0 emits every expression as written, 1 folds constant subexpressions,
+    // and 2 also simplifies arithmetic identities and drops branches that can
+    // never be taken.
+    pub opt_level: u8,
+}
+
 pub struct Wafer {
     pub imports: Vec<Import>,
+    pub globals: Vec<Global>,
     pub functions: Vec<Function>,
     pub data: Vec<u8>,
 }
 
+fn local_get(r#type: Type, index: usize) -> Instruction {
+    match r#type.value_type() {
+        ValueType::I32 => Instruction::LocalGetI32(index),
+        ValueType::I64 => Instruction::LocalGetI64(index),
+        ValueType::F32 => Instruction::LocalGetF32(index),
+        ValueType::F64 => Instruction::LocalGetF64(index),
+    }
+}
+
+fn local_set(r#type: Type, index: usize) -> Instruction {
+    match r#type.value_type() {
+        ValueType::I32 => Instruction::LocalSetI32(index),
+        ValueType::I64 => Instruction::LocalSetI64(index),
+        ValueType::F32 => Instruction::LocalSetF32(index),
+        ValueType::F64 => Instruction::LocalSetF64(index),
+    }
+}
+
+fn local_tee(r#type: Type, index: usize) -> Instruction {
+    match r#type.value_type() {
+        ValueType::I32 => Instruction::LocalTeeI32(index),
+        ValueType::I64 => Instruction::LocalTeeI64(index),
+        ValueType::F32 => Instruction::LocalTeeF32(index),
+        ValueType::F64 => Instruction::LocalTeeF64(index),
+    }
+}
+
+// Memory accesses are always aligned to the element's width.
+fn load(element: Element, offset: usize) -> Instruction {
+    match element {
+        Element::I32 => Instruction::LoadI32(2, offset),
+        Element::U16 => Instruction::Load16UnsignedI32(1, offset),
+        Element::U8 => Instruction::Load8UnsignedI32(0, offset),
+    }
+}
+
+fn store(element: Element, offset: usize) -> Instruction {
+    match element {
+        Element::I32 => Instruction::StoreI32(2, offset),
+        Element::U16 => Instruction::Store16I32(1, offset),
+        Element::U8 => Instruction::Store8I32(0, offset),
+    }
+}
+
+// Struct fields hold whole values, aligned to their size.
+fn load_field(r#type: Type, offset: usize) -> Instruction {
+    match r#type.value_type() {
+        ValueType::I32 => Instruction::LoadI32(2, offset),
+        ValueType::I64 => Instruction::LoadI64(3, offset),
+        ValueType::F32 => Instruction::LoadF32(2, offset),
+        ValueType::F64 => Instruction::LoadF64(3, offset),
+    }
+}
+
+fn store_field(r#type: Type, offset: usize) -> Instruction {
+    match r#type.value_type() {
+        ValueType::I32 => Instruction::StoreI32(2, offset),
+        ValueType::I64 => Instruction::StoreI64(3, offset),
+        ValueType::F32 => Instruction::StoreF32(2, offset),
+        ValueType::F64 => Instruction::StoreF64(3, offset),
+    }
+}
+
+// Returns `None` for the integer-only operators on floats, which have no
+// WebAssembly equivalent. The logical operators are lowered to `if` blocks
+// instead, see `collect_logical`.
+fn binary_instruction(operator: BinaryOperator, r#type: Type) -> Option<Instruction> {
+    let instruction = match (r#type, operator) {
+        (Type::I32, BinaryOperator::Add) => Instruction::AddI32,
+        (Type::I32, BinaryOperator::Subtract) => Instruction::SubtractI32,
+        (Type::I32, BinaryOperator::Multiply) => Instruction::MultiplyI32,
+        (Type::I32, BinaryOperator::Divide) => Instruction::DivideSignedI32,
+        (Type::I32, BinaryOperator::Equal) => Instruction::EqualI32,
+        (Type::I32, BinaryOperator::NotEqual) => Instruction::NotEqualI32,
+        (Type::I32, BinaryOperator::LessThanOrEqual) => Instruction::LessThanOrEqualSignedI32,
+        (Type::I32, BinaryOperator::LessThan) => Instruction::LessThanSignedI32,
+        (Type::I32, BinaryOperator::GreaterThanOrEqual) => Instruction::GreaterThanOrEqualSignedI32,
+        (Type::I32, BinaryOperator::GreaterThan) => Instruction::GreaterThanSignedI32,
+        (Type::I32, BinaryOperator::And) => Instruction::AndI32,
+        (Type::I32, BinaryOperator::Or) => Instruction::OrI32,
+        (Type::I32, BinaryOperator::Remainder) => Instruction::RemainderSignedI32,
+        (Type::I32, BinaryOperator::Xor) => Instruction::XorI32,
+        (Type::I32, BinaryOperator::ShiftLeft) => Instruction::ShiftLeftI32,
+        (Type::I32, BinaryOperator::ShiftRight) => Instruction::ShiftRightSignedI32,
+        (Type::I32, BinaryOperator::ShiftRightUnsigned) => Instruction::ShiftRightUnsignedI32,
+        (Type::I64, BinaryOperator::Add) => Instruction::AddI64,
+        (Type::I64, BinaryOperator::Subtract) => Instruction::SubtractI64,
+        (Type::I64, BinaryOperator::Multiply) => Instruction::MultiplyI64,
+        (Type::I64, BinaryOperator::Divide) => Instruction::DivideSignedI64,
+        (Type::I64, BinaryOperator::Equal) => Instruction::EqualI64,
+        (Type::I64, BinaryOperator::NotEqual) => Instruction::NotEqualI64,
+        (Type::I64, BinaryOperator::LessThanOrEqual) => Instruction::LessThanOrEqualSignedI64,
+        (Type::I64, BinaryOperator::LessThan) => Instruction::LessThanSignedI64,
+        (Type::I64, BinaryOperator::GreaterThanOrEqual) => Instruction::GreaterThanOrEqualSignedI64,
+        (Type::I64, BinaryOperator::GreaterThan) => Instruction::GreaterThanSignedI64,
+        (Type::I64, BinaryOperator::And) => Instruction::AndI64,
+        (Type::I64, BinaryOperator::Or) => Instruction::OrI64,
+        (Type::I64, BinaryOperator::Remainder) => Instruction::RemainderSignedI64,
+        (Type::I64, BinaryOperator::Xor) => Instruction::XorI64,
+        (Type::I64, BinaryOperator::ShiftLeft) => Instruction::ShiftLeftI64,
+        (Type::I64, BinaryOperator::ShiftRight) => Instruction::ShiftRightSignedI64,
+        (Type::I64, BinaryOperator::ShiftRightUnsigned) => Instruction::ShiftRightUnsignedI64,
+        (Type::F32, BinaryOperator::Add) => Instruction::AddF32,
+        (Type::F32, BinaryOperator::Subtract) => Instruction::SubtractF32,
+        (Type::F32, BinaryOperator::Multiply) => Instruction::MultiplyF32,
+        (Type::F32, BinaryOperator::Divide) => Instruction::DivideF32,
+        (Type::F32, BinaryOperator::Equal) => Instruction::EqualF32,
+        (Type::F32, BinaryOperator::NotEqual) => Instruction::NotEqualF32,
+        (Type::F32, BinaryOperator::LessThanOrEqual) => Instruction::LessThanOrEqualF32,
+        (Type::F32, BinaryOperator::LessThan) => Instruction::LessThanF32,
+        (Type::F32, BinaryOperator::GreaterThanOrEqual) => Instruction::GreaterThanOrEqualF32,
+        (Type::F32, BinaryOperator::GreaterThan) => Instruction::GreaterThanF32,
+        (Type::F64, BinaryOperator::Add) => Instruction::AddF64,
+        (Type::F64, BinaryOperator::Subtract) => Instruction::SubtractF64,
+        (Type::F64, BinaryOperator::Multiply) => Instruction::MultiplyF64,
+        (Type::F64, BinaryOperator::Divide) => Instruction::DivideF64,
+        (Type::F64, BinaryOperator::Equal) => Instruction::EqualF64,
+        (Type::F64, BinaryOperator::NotEqual) => Instruction::NotEqualF64,
+        (Type::F64, BinaryOperator::LessThanOrEqual) => Instruction::LessThanOrEqualF64,
+        (Type::F64, BinaryOperator::LessThan) => Instruction::LessThanF64,
+        (Type::F64, BinaryOperator::GreaterThanOrEqual) => Instruction::GreaterThanOrEqualF64,
+        (Type::F64, BinaryOperator::GreaterThan) => Instruction::GreaterThanF64,
+        (
+            Type::F32 | Type::F64,
+            BinaryOperator::Remainder
+            | BinaryOperator::And
+            | BinaryOperator::Or
+            | BinaryOperator::Xor
+            | BinaryOperator::ShiftLeft
+            | BinaryOperator::ShiftRight
+            | BinaryOperator::ShiftRightUnsigned,
+        ) => return None,
+        (Type::U32, BinaryOperator::Divide) => Instruction::DivideUnsignedI32,
+        (Type::U32, BinaryOperator::Remainder) => Instruction::RemainderUnsignedI32,
+        (Type::U32, BinaryOperator::LessThanOrEqual) => Instruction::LessThanOrEqualUnsignedI32,
+        (Type::U32, BinaryOperator::LessThan) => Instruction::LessThanUnsignedI32,
+        (Type::U32, BinaryOperator::GreaterThanOrEqual) => {
+            Instruction::GreaterThanOrEqualUnsignedI32
+        }
+        (Type::U32, BinaryOperator::GreaterThan) => Instruction::GreaterThanUnsignedI32,
+        (Type::U32, BinaryOperator::ShiftRight) => Instruction::ShiftRightUnsignedI32,
+        (Type::U64, BinaryOperator::Divide) => Instruction::DivideUnsignedI64,
+        (Type::U64, BinaryOperator::Remainder) => Instruction::RemainderUnsignedI64,
+        (Type::U64, BinaryOperator::LessThanOrEqual) => Instruction::LessThanOrEqualUnsignedI64,
+        (Type::U64, BinaryOperator::LessThan) => Instruction::LessThanUnsignedI64,
+        (Type::U64, BinaryOperator::GreaterThanOrEqual) => {
+            Instruction::GreaterThanOrEqualUnsignedI64
+        }
+        (Type::U64, BinaryOperator::GreaterThan) => Instruction::GreaterThanUnsignedI64,
+        (Type::U64, BinaryOperator::ShiftRight) => Instruction::ShiftRightUnsignedI64,
+        // The remaining operators don't depend on the sign.
+        (Type::U32, operator) => return binary_instruction(operator, Type::I32),
+        (Type::U64, operator) => return binary_instruction(operator, Type::I64),
+        (Type::Array(_) | Type::Struct(_) | Type::Enum(_), operator) => {
+            return binary_instruction(operator, Type::I32);
+        }
+        (_, BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr) => {
+            unreachable!("logical operators have no instruction")
+        }
+    };
+
+    Some(instruction)
+}
+
+// Multiplying by 1 is dropped by the identities instead.
+fn is_power_of_two(factor: i128) -> bool {
+    factor > 1 && factor.count_ones() == 1
+}
+
+fn conversion_instruction(source: Type, target: Type) -> Option<Instruction> {
+    match (source, target) {
+        (Type::I64 | Type::U64, Type::I32 | Type::U32) => Some(Instruction::WrapI64),
+        (Type::F32, Type::I32) => Some(Instruction::TruncateSignedF32ToI32),
+        (Type::F32, Type::U32) => Some(Instruction::TruncateUnsignedF32ToI32),
+        (Type::F64, Type::I32) => Some(Instruction::TruncateSignedF64ToI32),
+        (Type::F64, Type::U32) => Some(Instruction::TruncateUnsignedF64ToI32),
+        (Type::I32, Type::I64 | Type::U64) => Some(Instruction::ExtendSignedI32),
+        (Type::U32, Type::I64 | Type::U64) => Some(Instruction::ExtendUnsignedI32),
+        (Type::F32, Type::I64) => Some(Instruction::TruncateSignedF32ToI64),
+        (Type::F32, Type::U64) => Some(Instruction::TruncateUnsignedF32ToI64),
+        (Type::F64, Type::I64) => Some(Instruction::TruncateSignedF64ToI64),
+        (Type::F64, Type::U64) => Some(Instruction::TruncateUnsignedF64ToI64),
+        (Type::I32, Type::F32) => Some(Instruction::ConvertSignedI32ToF32),
+        (Type::U32, Type::F32) => Some(Instruction::ConvertUnsignedI32ToF32),
+        (Type::I64, Type::F32) => Some(Instruction::ConvertSignedI64ToF32),
+        (Type::U64, Type::F32) => Some(Instruction::ConvertUnsignedI64ToF32),
+        (Type::F64, Type::F32) => Some(Instruction::DemoteF64),
+        (Type::I32, Type::F64) => Some(Instruction::ConvertSignedI32ToF64),
+        (Type::U32, Type::F64) => Some(Instruction::ConvertUnsignedI32ToF64),
+        (Type::I64, Type::F64) => Some(Instruction::ConvertSignedI64ToF64),
+        (Type::U64, Type::F64) => Some(Instruction::ConvertUnsignedI64ToF64),
+        (Type::F32, Type::F64) => Some(Instruction::PromoteF32),
+        // Signed and unsigned integers of the same width share their bits.
+        _ => None,
+    }
+}
+
+// Label depths of the constructs `break` and `continue` branch to.
+struct Loop {
+    exit: usize,
+    repeat: usize,
+}
+
 struct InstructionCollector<'a> {
     name: &'a str,
+    result: Option<Type>,
     symbols: &'a Symbols,
-    strings: &'a Strings,
+    data: &'a Data,
     instructions: Vec<Instruction>,
+    errors: Vec<CompileError>,
+    // The number of enclosing structured instructions, for resolving branch
+    // labels.
+    depth: usize,
+    loops: Vec<Loop>,
+    opt_level: u8,
+    shadow: Option<ShadowFrame>,
+}
+
+impl Environment for InstructionCollector<'_> {
+    fn variable(&self, identifier: &Identifier) -> Option<Type> {
+        self.symbols.variable_type(self.name, identifier)
+    }
+
+    fn result(&self, function: &str) -> Option<Type> {
+        self.symbols.result(function)
+    }
+
+    fn field(&self, r#type: Type, field: &str) -> Option<Type> {
+        self.symbols.field(r#type, field).map(|field| field.r#type)
+    }
+
+    fn type_names(&self) -> &TypeNames {
+        self.symbols.type_names()
+    }
+}
+
+impl Constants for InstructionCollector<'_> {
+    fn constant(&self, identifier: &Identifier) -> Option<Value> {
+        match self.symbols.variable(self.name, identifier) {
+            Some(Variable::Constant(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn string(&self, value: &str) -> Option<i32> {
+        self.data.string(value)
+    }
 }
 
 impl<'a> InstructionCollector<'a> {
-    fn new(name: &'a str, symbols: &'a Symbols, strings: &'a Strings) -> Self {
+    fn new(name: &'a str, symbols: &'a Symbols, data: &'a Data, options: Options) -> Self {
         Self {
             name,
+            result: symbols.result(name),
             symbols,
-            strings,
+            data,
             instructions: vec![],
+            errors: vec![],
+            depth: 0,
+            loops: vec![],
+            opt_level: options.opt_level,
+            shadow: None,
         }
     }
 
-    fn collect_inner(&mut self, pair: Pair<Rule>) {
-        match pair.as_rule() {
-            Rule::block_expression | Rule::block_statements => {
-                for pair in pair.into_inner() {
-                    self.collect_inner(pair);
-                }
-            }
-            Rule::let_statement => {
-                let mut pairs = pair.into_inner();
+    fn enter(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+        self.depth += 1;
+    }
 
-                let identifier = pairs.next().unwrap().as_str();
-                let (r#type, index) = self.symbols.local(self.name, identifier);
+    fn exit(&mut self) {
+        self.instructions.push(Instruction::End);
+        self.depth -= 1;
+    }
 
-                let expression = pairs.next().unwrap();
+    fn collect_loop_branch(&mut self, keyword: &str, span: Span) {
+        let Some(Loop { exit, repeat }) = self.loops.last() else {
+            self.errors.push(CompileError::OutsideLoop {
+                keyword: keyword.to_string(),
+                span,
+            });
 
-                self.collect_inner(expression);
+            return;
+        };
 
-                match r#type {
-                    ValueType::I32 => {
-                        self.instructions.push(Instruction::LocalSetI32(index));
-                    }
+        let level = if keyword == "break" { exit } else { repeat };
+        self.instructions
+            .push(Instruction::Break(self.depth - level - 1));
+    }
+
+    fn local(&self, identifier: &Identifier) -> Option<(Type, usize)> {
+        self.symbols.local(self.name, identifier)
+    }
+
+    fn function(&mut self, identifier: &Identifier, args: usize) -> Option<usize> {
+        let Some(index) = self.symbols.function(&identifier.name) else {
+            self.errors.push(CompileError::UnknownFunction {
+                name: identifier.name.clone(),
+                span: identifier.span,
+            });
+
+            return None;
+        };
+
+        self.check_arity(
+            identifier,
+            self.symbols.parameters(&identifier.name).len(),
+            args,
+        )
+        .then_some(index)
+    }
+
+    fn check_arity(&mut self, identifier: &Identifier, expected: usize, args: usize) -> bool {
+        if expected != args {
+            self.errors.push(CompileError::ArityMismatch {
+                name: identifier.name.clone(),
+                expected,
+                found: args,
+                span: identifier.span,
+            });
+        }
+
+        expected == args
+    }
+
+    fn collect_block(&mut self, block: &Block, expected: Type) {
+        for stmt in &block.statements {
+            self.collect_statement(stmt);
+        }
+
+        if let Some(result) = &block.result {
+            self.collect_expression(result, expected);
+        }
+    }
+
+    // Only a call to another void function can end a void block.
+    fn collect_void_block(&mut self, block: &Block) {
+        for stmt in &block.statements {
+            self.collect_statement(stmt);
+        }
+
+        if let Some(result) = &block.result {
+            self.collect_expression_statement(result);
+        }
+    }
+
+    fn collect_statement(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let { name, value, .. } => {
+                if let Some((r#type, index)) = self.local(name) {
+                    self.collect_expression(value, r#type);
+                    self.instructions.push(local_set(r#type, index));
                 }
             }
-            Rule::if_statement => {
-                let mut pairs = pair.into_inner();
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                if let Some(condition) = self.constant_condition(condition) {
+                    self.collect_taken_branch(
+                        condition,
+                        then_block,
+                        else_block.as_ref(),
+                        Type::I32,
+                    );
+                    return;
+                }
 
-                let condition = pairs.next().unwrap();
-                self.collect_inner(condition);
-                self.instructions.push(Instruction::If(None));
+                self.collect_expression(condition, Type::I32);
+                self.enter(Instruction::If(None));
 
-                let then_block = pairs.next().unwrap();
-                self.collect_inner(then_block);
+                self.collect_block(then_block, Type::I32);
 
-                if let Some(else_block) = pairs.next() {
+                if let Some(else_block) = else_block {
                     self.instructions.push(Instruction::Else);
-                    self.collect_inner(else_block);
+                    self.collect_block(else_block, Type::I32);
                 }
 
-                self.instructions.push(Instruction::End);
+                self.exit();
             }
-            Rule::while_statement => {
-                self.instructions.push(Instruction::Loop(None));
+            StmtKind::While { condition, body } => {
+                let level = self.depth;
 
-                let mut pairs = pair.into_inner();
+                self.enter(Instruction::Block(None));
+                self.enter(Instruction::Loop(None));
 
-                let condition = pairs.next().unwrap();
-                self.collect_inner(condition);
+                self.collect_expression(condition, Type::I32);
 
-                self.instructions.push(Instruction::If(None));
+                self.instructions.push(Instruction::EqualZeroI32);
+                self.instructions.push(Instruction::BreakIf(1));
 
-                let body = pairs.next().unwrap();
-                self.collect_inner(body);
+                self.loops.push(Loop {
+                    exit: level,
+                    repeat: level + 1,
+                });
+                self.collect_block(body, Type::I32);
+                self.loops.pop();
 
-                self.instructions.push(Instruction::Break(1));
-                self.instructions.push(Instruction::End);
-                self.instructions.push(Instruction::End);
+                self.instructions.push(Instruction::Break(0));
+                self.exit();
+                self.exit();
             }
-            Rule::expression_statement => {
-                let expression = pair.into_inner().next().unwrap();
-                self.collect_inner(expression);
+            StmtKind::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                if let Some(local) = self.local(variable) {
+                    self.collect_for(local, iterable, body, stmt.span);
+                }
+            }
+            StmtKind::Break => self.collect_loop_branch("break", stmt.span),
+            StmtKind::Continue => self.collect_loop_branch("continue", stmt.span),
+            StmtKind::Return(value) => {
+                if let (Some(value), Some(result)) = (value, self.result) {
+                    self.collect_expression(value, result);
+                }
 
-                self.instructions.push(Instruction::Drop);
+                self.collect_epilogue();
+                self.instructions.push(Instruction::Return);
             }
-            Rule::variable_assignment_expression => {
-                let mut pairs = pair.into_inner();
+            StmtKind::Expr(expr) => self.collect_expression_statement(expr),
+        }
+    }
 
-                let identifier = pairs.next().unwrap().as_str();
-                let (r#type, index) = self.symbols.local(self.name, identifier);
+    fn collect_expression_statement(&mut self, expr: &Expr) {
+        if let ExprKind::Match { value, arms } = &expr.kind {
+            self.collect_match(value, arms, None, expr.span);
+            return;
+        }
 
-                let expression = pairs.next().unwrap();
+        let r#type = types::infer(expr, self).unwrap_or_else(|| types::default_type(expr));
+        self.collect_expression(expr, r#type);
 
-                self.collect_inner(expression);
+        if !self.symbols.is_void_call(expr) {
+            self.instructions.push(Instruction::Drop);
+        }
+    }
 
-                match r#type {
-                    ValueType::I32 => {
-                        self.instructions.push(Instruction::LocalTeeI32(index));
-                    }
-                }
+    fn hidden_local(&self, name: &str, span: Span) -> (Type, usize) {
+        self.symbols
+            .hidden_local(self.name, &hidden_name(name, span))
+            .expect("for loops always allocate their hidden locals")
+    }
+
+    // `for` loops are lowered like `while`, except that the body sits in its
+    // own `block` so that `continue` still runs the increment.
+    fn collect_for(
+        &mut self,
+        (r#type, variable): (Type, usize),
+        iterable: &Iterable,
+        body: &Block,
+        span: Span,
+    ) {
+        let (counter_type, counter, bound) = match iterable {
+            Iterable::Range { start, end } => {
+                let (_, end_index) = self.hidden_local("end", span);
+
+                self.collect_expression(start, r#type);
+                self.instructions.push(local_set(r#type, variable));
+                self.collect_expression(end, r#type);
+                self.instructions.push(local_set(r#type, end_index));
+
+                (r#type, variable, vec![local_get(r#type, end_index)])
             }
-            Rule::array_assignment_expression => {
-                let mut pairs = pair.into_inner();
+            Iterable::Array(array) => {
+                let (_, array_index) = self.hidden_local("array", span);
+                let (_, index) = self.hidden_local("index", span);
 
-                let mut array = pairs.next().unwrap().into_inner();
-                let identifier = array.next().unwrap().as_str();
-                let index = array.next().unwrap();
-                let expression = pairs.next().unwrap();
+                self.collect_expression(array, Type::I32);
+                self.instructions
+                    .push(Instruction::LocalSetI32(array_index));
+                self.instructions.push(Instruction::ConstI32(0));
+                self.instructions.push(Instruction::LocalSetI32(index));
+
+                (
+                    Type::I32,
+                    index,
+                    vec![
+                        Instruction::LocalGetI32(array_index),
+                        Instruction::LoadI32(2, 0),
+                    ],
+                )
+            }
+        };
+        let level = self.depth;
 
-                if identifier == "__mem" {
-                    self.collect_inner(index);
-                    self.collect_inner(expression);
+        self.enter(Instruction::Block(None));
+        self.enter(Instruction::Loop(None));
 
-                    let (r#type, temp_index) = self.symbols.local(self.name, "$temp");
+        self.instructions.push(local_get(counter_type, counter));
+        self.instructions.extend(bound);
+        self.instructions.push(
+            binary_instruction(BinaryOperator::LessThan, counter_type)
+                .expect("comparisons exist for every type"),
+        );
+        self.instructions.push(Instruction::EqualZeroI32);
+        self.instructions.push(Instruction::BreakIf(1));
 
-                    match r#type {
-                        ValueType::I32 => {
-                            self.instructions.push(Instruction::LocalTeeI32(temp_index));
-                        }
-                    }
+        // The element is read with a 4 byte offset to skip the length header;
+        // the loop condition already keeps the index in bounds.
+        if let Iterable::Array(_) = iterable {
+            let (array_type, array_index) = self.hidden_local("array", span);
+            let element = array_type.element();
+
+            self.instructions
+                .push(Instruction::LocalGetI32(array_index));
+            self.instructions.push(Instruction::LocalGetI32(counter));
+
+            if element.size() > 1 {
+                self.instructions
+                    .push(Instruction::ConstI32(element.size()));
+                self.instructions.push(Instruction::MultiplyI32);
+            }
 
-                    self.instructions.push(Instruction::StoreI32(2, 0));
+            self.instructions.push(Instruction::AddI32);
+            self.instructions.push(load(element, 4));
+            self.instructions.push(Instruction::LocalSetI32(variable));
+        }
 
-                    match r#type {
-                        ValueType::I32 => {
-                            self.instructions.push(Instruction::LocalGetI32(temp_index));
+        self.loops.push(Loop {
+            exit: level,
+            repeat: level + 2,
+        });
+        self.enter(Instruction::Block(None));
+        self.collect_block(body, Type::I32);
+        self.exit();
+        self.loops.pop();
+
+        self.instructions.push(local_get(counter_type, counter));
+        self.instructions.push(match counter_type {
+            Type::I64 | Type::U64 => Instruction::ConstI64(1),
+            _ => Instruction::ConstI32(1),
+        });
+        self.instructions.push(
+            binary_instruction(BinaryOperator::Add, counter_type)
+                .expect("addition exists for every type"),
+        );
+        self.instructions.push(local_set(counter_type, counter));
+
+        self.instructions.push(Instruction::Break(0));
+        self.exit();
+        self.exit();
+    }
+
+    // `expected` is the type the surrounding code needs on the stack; it only
+    // decides anything for literals, whose type comes from context.
+    fn collect_expression(&mut self, expr: &Expr, expected: Type) {
+        if self.collect_folded(expr, expected) {
+            return;
+        }
+
+        match &expr.kind {
+            ExprKind::Assign { target, value } => match self.symbols.variable(self.name, target) {
+                Some(Variable::Local(r#type, index)) => {
+                    self.collect_expression(value, r#type);
+                    self.instructions.push(local_tee(r#type, index));
+                }
+                Some(Variable::Global(r#type, index)) => {
+                    self.collect_expression(value, r#type);
+                    self.instructions.push(Instruction::GlobalSet(index));
+                    self.instructions.push(Instruction::GlobalGet(index));
+                }
+                Some(Variable::Constant(_)) | None => self.collect_expression(value, expected),
+            },
+            ExprKind::IndexAssign {
+                array,
+                index,
+                value,
+            } => {
+                if let Some(element) = Element::from_memory(array) {
+                    self.collect_expression(index, Type::I32);
+                    self.collect_expression(value, Type::I32);
+
+                    let (r#type, temp_index) = self
+                        .symbols
+                        .hidden_local(self.name, &temp_local(Type::I32))
+                        .expect("array assignments always allocate a temporary");
+
+                    self.instructions.push(local_tee(r#type, temp_index));
+                    self.instructions.push(store(element, 0));
+                    self.instructions.push(local_get(r#type, temp_index));
+                } else {
+                    let element = self.array_element(array);
+
+                    self.collect_expression(array, Type::Array(element));
+                    self.collect_expression(index, Type::I32);
+                    self.collect_expression(value, Type::I32);
+                    self.collect_runtime_call(
+                        &format!("__write{}", element.array_name()),
+                        expr.span,
+                    );
+                }
+            }
+            ExprKind::Unary { operator, operand } => {
+                self.collect_unary(*operator, operand, expected)
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } if types::is_logical(*operator) => self.collect_logical(*operator, left, right),
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                let operands = types::infer(left, self)
+                    .or_else(|| types::infer(right, self))
+                    .unwrap_or_else(|| {
+                        if types::is_comparison(*operator) {
+                            types::default_operands(left, right)
+                        } else {
+                            expected
                         }
+                    });
+
+                if self.collect_simplified(*operator, left, right, operands) {
+                    return;
+                }
+
+                self.collect_expression(left, operands);
+                self.collect_expression(right, operands);
+
+                // Type errors have already been reported by the checker, so
+                // ill-typed code only needs to be replaced with `unreachable`.
+                self.instructions.push(
+                    binary_instruction(*operator, operands).unwrap_or(Instruction::Unreachable),
+                );
+            }
+            ExprKind::Call {
+                function,
+                arguments,
+            } => match function.name.as_str() {
+                "__trap" => self.instructions.push(Instruction::Unreachable),
+                "__memorySize" => {
+                    self.collect_intrinsic(function, arguments, 0, Instruction::MemorySize)
+                }
+                "__memoryGrow" => {
+                    self.collect_intrinsic(function, arguments, 1, Instruction::MemoryGrow)
+                }
+                "concat" => self.collect_concat(function, arguments, expr.span),
+                "len" => self.collect_len(function, arguments),
+                "i32" | "u32" | "i64" | "u64" | "f32" | "f64" => {
+                    self.collect_conversion(function, arguments)
+                }
+                _ => {
+                    let index = self.function(function, arguments.len());
+                    let parameters = match index {
+                        Some(_) => self.symbols.parameters(&function.name),
+                        None => vec![Type::I32; arguments.len()],
+                    };
+
+                    for (argument, r#type) in arguments.iter().zip(parameters) {
+                        self.collect_expression(argument, r#type);
                     }
-                } else {
-                    let (r#type, ident_index) = self.symbols.local(self.name, identifier);
 
-                    match r#type {
-                        ValueType::I32 => {
-                            self.instructions
-                                .push(Instruction::LocalGetI32(ident_index));
-                        }
+                    match index {
+                        Some(index) => self.collect_call(index, &function.name),
+                        None => self.instructions.push(Instruction::Unreachable),
                     }
+                }
+            },
+            ExprKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let r#type = types::infer(expr, self).unwrap_or(expected);
+
+                if let Some(condition) = self.constant_condition(condition) {
+                    self.collect_taken_branch(condition, then_block, Some(else_block), r#type);
+                    return;
+                }
+
+                self.collect_expression(condition, Type::I32);
+
+                self.enter(Instruction::If(Some(r#type.value_type())));
 
-                    self.collect_inner(index);
-                    self.collect_inner(expression);
+                self.collect_block(then_block, r#type);
 
-                    let function_index = self.symbols.function("__writeInt32Array");
-                    self.instructions.push(Instruction::Call(function_index));
+                self.instructions.push(Instruction::Else);
+
+                self.collect_block(else_block, r#type);
+
+                self.exit();
+            }
+            ExprKind::Index { array, index } => {
+                if let Some(element) = Element::from_memory(array) {
+                    self.collect_expression(index, Type::I32);
+
+                    self.instructions.push(load(element, 0));
+                } else {
+                    let element = self.array_element(array);
+
+                    self.collect_expression(array, Type::Array(element));
+                    self.collect_expression(index, Type::I32);
+                    self.collect_runtime_call(
+                        &format!("__read{}", element.array_name()),
+                        expr.span,
+                    );
                 }
             }
-            Rule::binary_expression => {
-                let mut pairs = pair.into_inner();
-                self.collect_inner(pairs.next().unwrap());
+            ExprKind::Field { object, field } => {
+                let object_type = types::infer(object, self).unwrap_or(Type::I32);
+                self.collect_expression(object, object_type);
 
-                while let Some(operation) = pairs.next() {
-                    let operand = pairs.next().unwrap();
+                // Unknown fields have already been reported by the checker.
+                self.instructions
+                    .push(match self.symbols.field(object_type, &field.name) {
+                        Some(field) => load_field(field.r#type, field.offset),
+                        None => Instruction::Unreachable,
+                    });
+            }
+            ExprKind::FieldAssign {
+                object,
+                field,
+                value,
+            } => {
+                let object_type = types::infer(object, self).unwrap_or(Type::I32);
+
+                let Some(field) = self.symbols.field(object_type, &field.name) else {
+                    self.instructions.push(Instruction::Unreachable);
+                    return;
+                };
+
+                let (r#type, temp_index) = self
+                    .symbols
+                    .hidden_local(self.name, &temp_local(field.r#type))
+                    .expect("field assignments always allocate a temporary");
+
+                self.collect_expression(object, object_type);
+                self.collect_expression(value, field.r#type);
+                self.instructions.push(local_tee(r#type, temp_index));
+                self.instructions
+                    .push(store_field(field.r#type, field.offset));
+                self.instructions.push(local_get(r#type, temp_index));
+            }
+            ExprKind::Struct { name, fields } => self.collect_struct(name, fields, expr.span),
+            ExprKind::Array(elements) => self.collect_array(elements, expr.span),
+            ExprKind::Variant { r#enum, variant } => {
+                let discriminant = self
+                    .symbols
+                    .type_name(&r#enum.name)
+                    .and_then(|name| self.symbols.variant(Type::Enum(name), &variant.name));
+
+                // Unknown variants have already been reported by the checker.
+                self.instructions.push(match discriminant {
+                    Some(discriminant) => Instruction::ConstI32(discriminant),
+                    None => Instruction::Unreachable,
+                });
+            }
+            ExprKind::Match { value, arms } => {
+                let r#type = types::infer(expr, self).unwrap_or(expected);
 
-                    self.collect_inner(operand);
-                    self.collect_inner(operation);
+                self.collect_match(value, arms, Some(r#type), expr.span);
+            }
+            ExprKind::Variable(identifier) => {
+                if identifier.name == "__heap_base" {
+                    self.instructions
+                        .push(Instruction::ConstI32(self.data.len()));
+                } else {
+                    self.collect_identifier(identifier);
                 }
             }
-            Rule::call_expression => {
-                let mut pairs = pair.into_inner();
+            ExprKind::Number(number) => self.instructions.push(
+                Value::from_number(*number, expected)
+                    .map_or(Instruction::Unreachable, Value::instruction),
+            ),
+            ExprKind::Float(number) => match expected {
+                Type::F32 => self
+                    .instructions
+                    .push(Instruction::ConstF32(*number as f32)),
+                Type::F64 => self.instructions.push(Instruction::ConstF64(*number)),
+                _ => self.instructions.push(Instruction::Unreachable),
+            },
+            ExprKind::String(value) => {
+                let offset = self
+                    .data
+                    .string(value)
+                    .expect("strings are collected from the same module");
 
-                let identifier = pairs.next().unwrap().as_str();
+                self.instructions.push(Instruction::ConstI32(offset));
+            }
+        }
+    }
 
-                if identifier == "__trap" {
-                    self.instructions.push(Instruction::Unreachable);
-                } else {
-                    let index = self.symbols.function(identifier);
+    // From level 1, unary and binary operators whose operands are known at
+    // compile time are replaced by their result. Expressions that would trap,
+    // like a division by zero, can't be evaluated and are left to trap at
+    // runtime.
+    fn collect_folded(&mut self, expr: &Expr, expected: Type) -> bool {
+        if self.opt_level < 1
+            || !matches!(expr.kind, ExprKind::Unary { .. } | ExprKind::Binary { .. })
+        {
+            return false;
+        }
 
-                    let args = pairs.next().unwrap();
+        match constants::evaluate(expr, expected, self) {
+            Ok(value) => {
+                self.instructions.push(value.instruction());
+                true
+            }
+            Err(_) => false,
+        }
+    }
 
-                    for expression in args.into_inner() {
-                        self.collect_inner(expression);
-                    }
+    // From level 2, adding 0 and multiplying by 1 are dropped, and multiplying
+    // by a power of two becomes a shift. Floats are left alone, since adding
+    // 0.0 turns -0.0 into 0.0.
+    fn collect_simplified(
+        &mut self,
+        operator: BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+        operands: Type,
+    ) -> bool {
+        if self.opt_level < 2 || operands.is_float() {
+            return false;
+        }
 
-                    self.instructions.push(Instruction::Call(index));
-                }
+        let integer = |expr| {
+            constants::evaluate(expr, operands, self)
+                .ok()
+                .and_then(Value::integer)
+        };
+
+        let (operand, shift) = match (operator, integer(left), integer(right)) {
+            (BinaryOperator::Add | BinaryOperator::Subtract, _, Some(0))
+            | (BinaryOperator::Multiply | BinaryOperator::Divide, _, Some(1)) => (left, None),
+            (BinaryOperator::Add, Some(0), _) | (BinaryOperator::Multiply, Some(1), _) => {
+                (right, None)
+            }
+            (BinaryOperator::Multiply, _, Some(factor)) if is_power_of_two(factor) => {
+                (left, Some(factor.trailing_zeros()))
+            }
+            (BinaryOperator::Multiply, Some(factor), _) if is_power_of_two(factor) => {
+                (right, Some(factor.trailing_zeros()))
             }
-            Rule::if_expression => {
-                let mut pairs = pair.into_inner();
+            _ => return false,
+        };
 
-                let condition = pairs.next().unwrap();
-                self.collect_inner(condition);
+        self.collect_expression(operand, operands);
 
-                self.instructions
-                    .push(Instruction::If(Some(ValueType::I32)));
+        if let Some(shift) = shift {
+            let shift = Value::from_number(shift.into(), operands)
+                .expect("shifts are smaller than the bit width");
 
-                let then_block = pairs.next().unwrap();
-                self.collect_inner(then_block);
+            self.instructions.push(shift.instruction());
+            self.instructions.push(
+                binary_instruction(BinaryOperator::ShiftLeft, operands)
+                    .expect("integers can be shifted"),
+            );
+        }
 
-                self.instructions.push(Instruction::Else);
+        true
+    }
 
-                let else_block = pairs.next().unwrap();
-                self.collect_inner(else_block);
-
-                self.instructions.push(Instruction::End);
-            }
-            Rule::binary_operation => self.instructions.push(match pair.as_str() {
-                "+" => Instruction::AddI32,
-                "-" => Instruction::SubtractI32,
-                "*" => Instruction::MultiplyI32,
-                "/" => Instruction::DivideSignedI32,
-                "==" => Instruction::EqualI32,
-                "!=" => Instruction::NotEqualI32,
-                "<=" => Instruction::LessThanOrEqualSignedI32,
-                "<" => Instruction::LessThanSignedI32,
-                ">=" => Instruction::GreaterThanOrEqualSignedI32,
-                ">" => Instruction::GreaterThanSignedI32,
-                "and" => Instruction::AndI32,
-                "or" => Instruction::OrI32,
-                _ => unreachable!(),
-            }),
-            Rule::array_index => {
-                let mut pairs = pair.into_inner();
+    // From level 2, conditions known at compile time pick their branch.
+    fn constant_condition(&self, condition: &Expr) -> Option<bool> {
+        if self.opt_level < 2 {
+            return None;
+        }
 
-                let identifier = pairs.next().unwrap().as_str();
-                let index = pairs.next().unwrap();
+        match constants::evaluate(condition, Type::I32, self) {
+            Ok(Value::I32(condition)) => Some(condition != 0),
+            _ => None,
+        }
+    }
 
-                if identifier == "__mem" {
-                    self.collect_inner(index);
+    // Only the branch that is taken is emitted, without an `if` around it. The
+    // other one is still compiled for the errors it reports, then dropped.
+    fn collect_taken_branch(
+        &mut self,
+        condition: bool,
+        then_block: &Block,
+        else_block: Option<&Block>,
+        expected: Type,
+    ) {
+        let (taken, skipped) = if condition {
+            (Some(then_block), else_block)
+        } else {
+            (else_block, Some(then_block))
+        };
+
+        if let Some(taken) = taken {
+            self.collect_block(taken, expected);
+        }
 
-                    self.instructions.push(Instruction::LoadI32(2, 0));
-                } else {
-                    let (r#type, ident_index) = self.symbols.local(self.name, identifier);
+        if let Some(skipped) = skipped {
+            let start = self.instructions.len();
 
-                    match r#type {
-                        ValueType::I32 => {
-                            self.instructions
-                                .push(Instruction::LocalGetI32(ident_index));
-                        }
-                    }
+            self.collect_block(skipped, expected);
+            self.instructions.truncate(start);
+        }
+    }
 
-                    self.collect_inner(index);
+    // Struct literals are allocated on the heap, and their fields stored in the
+    // order they are written.
+    fn collect_struct(&mut self, name: &Identifier, fields: &[(Identifier, Expr)], span: Span) {
+        let r#type = self.symbols.type_name(&name.name).map(Type::Struct);
+
+        let Some(symbol) = r#type.and_then(|r#type| self.symbols.r#struct(r#type)) else {
+            self.instructions.push(Instruction::Unreachable);
+            return;
+        };
+
+        let (_, local) = self
+            .symbols
+            .hidden_local(self.name, &hidden_name("struct", span))
+            .expect("struct literals always allocate a local");
+
+        let r#type = Type::Struct(symbol.name);
+
+        self.instructions
+            .push(Instruction::ConstI32(symbol.size as i32));
+        self.collect_runtime_call("alloc", span);
+        self.instructions.push(Instruction::LocalSetI32(local));
+
+        for (field, value) in fields {
+            let Some(field) = self.symbols.field(r#type, &field.name) else {
+                continue;
+            };
+
+            self.instructions.push(Instruction::LocalGetI32(local));
+            self.collect_expression(value, field.r#type);
+            self.instructions
+                .push(store_field(field.r#type, field.offset));
+        }
 
-                    let function_index = self.symbols.function("__readInt32Array");
-                    self.instructions.push(Instruction::Call(function_index));
-                }
-            }
-            Rule::identifier => {
-                let identifier = pair.as_str();
+        self.instructions.push(Instruction::LocalGetI32(local));
+    }
 
-                if identifier == "__heap_base" {
-                    self.instructions
-                        .push(Instruction::ConstI32(self.strings.len()));
-                } else {
-                    let (r#type, index) = self.symbols.local(self.name, identifier);
+    // Constant array literals are already in the data segment, shared by every
+    // evaluation. Others are allocated on the heap, laid out like the prelude's
+    // `Int32Array`s.
+    fn collect_array(&mut self, elements: &[Expr], span: Span) {
+        if let Some(offset) = self.data.array(span) {
+            self.instructions.push(Instruction::ConstI32(offset));
+            return;
+        }
 
-                    match r#type {
-                        ValueType::I32 => {
-                            self.instructions.push(Instruction::LocalGetI32(index));
-                        }
+        let (_, local) = self
+            .symbols
+            .hidden_local(self.name, &hidden_name("elements", span))
+            .expect("array literals always allocate a local");
+
+        self.instructions
+            .push(Instruction::ConstI32((elements.len() as i32 + 1) * 4));
+        self.collect_runtime_call("alloc", span);
+        self.instructions.push(Instruction::LocalTeeI32(local));
+        self.instructions
+            .push(Instruction::ConstI32(elements.len() as i32));
+        self.instructions.push(store(Element::I32, 0));
+
+        for (index, element) in elements.iter().enumerate() {
+            self.instructions.push(Instruction::LocalGetI32(local));
+            self.collect_expression(element, Type::I32);
+            self.instructions.push(store(Element::I32, (index + 1) * 4));
+        }
+
+        self.instructions.push(Instruction::LocalGetI32(local));
+    }
+
+    // Each arm gets a block, nested inside the one for the `_` arm and then
+    // the match as a whole, with the first arm innermost. The dispatch at the
+    // center branches out of the block just before the chosen arm, which then
+    // branches out of the match with its value. Dense patterns dispatch
+    // through a jump table, and sparse ones compare the value with each.
+    // Matches in statement position have no type, and neither do their arms.
+    fn collect_match(&mut self, value: &Expr, arms: &[Arm], r#type: Option<Type>, span: Span) {
+        let (_, local) = self
+            .symbols
+            .hidden_local(self.name, &hidden_name("match", span))
+            .expect("match expressions always allocate a local");
+
+        // The first arm for a value wins, and arms after a `_` are never
+        // reached. Unknown patterns have already been reported by the checker.
+        let mut cases: Vec<(i32, &Block)> = vec![];
+        let mut default = None;
+
+        for arm in arms {
+            match &arm.pattern {
+                Pattern::Wildcard(_) => {
+                    default = Some(&arm.body);
+                    break;
+                }
+                Pattern::Value(pattern) => {
+                    if let Some(value) = self.symbols.pattern(pattern)
+                        && cases.iter().all(|(case, _)| *case != value)
+                    {
+                        cases.push((value, &arm.body));
                     }
                 }
             }
-            Rule::number => {
-                let number = i32::from_str(pair.as_str()).expect("failed to parse number");
-                self.instructions.push(Instruction::ConstI32(number));
+        }
+
+        let level = self.depth;
+
+        self.enter(Instruction::Block(r#type.map(Type::value_type)));
+        self.enter(Instruction::Block(None));
+
+        for _ in &cases {
+            self.enter(Instruction::Block(None));
+        }
+
+        let value_type = types::infer(value, self).unwrap_or(Type::I32);
+        self.collect_expression(value, value_type);
+        self.instructions.push(Instruction::LocalSetI32(local));
+        self.collect_dispatch(local, &cases);
+
+        for (_, body) in cases {
+            self.exit();
+            self.collect_arm(body, r#type);
+            self.instructions
+                .push(Instruction::Break(self.depth - level - 1));
+        }
+
+        self.exit();
+
+        match default {
+            Some(body) => self.collect_arm(body, r#type),
+            None => self.instructions.push(Instruction::Unreachable),
+        }
+
+        self.exit();
+    }
+
+    fn collect_arm(&mut self, body: &Block, r#type: Option<Type>) {
+        match r#type {
+            Some(r#type) => self.collect_block(body, r#type),
+            None => self.collect_void_block(body),
+        }
+    }
+
+    // Branches to the label of the matching case, or past all of them to the
+    // default.
+    fn collect_dispatch(&mut self, local: usize, cases: &[(i32, &Block)]) {
+        let default = cases.len();
+        let values = cases.iter().map(|(value, _)| i64::from(*value));
+
+        let (Some(min), Some(max)) = (values.clone().min(), values.max()) else {
+            self.instructions.push(Instruction::Break(default));
+            return;
+        };
+
+        if max - min < 2 * cases.len() as i64 {
+            let labels = (min..=max)
+                .map(|value| {
+                    cases
+                        .iter()
+                        .position(|(case, _)| i64::from(*case) == value)
+                        .unwrap_or(default)
+                })
+                .collect();
+
+            // Values below `min` wrap around to large unsigned indices, which
+            // also go to the default.
+            self.instructions.push(Instruction::LocalGetI32(local));
+            self.instructions.push(Instruction::ConstI32(min as i32));
+            self.instructions.push(Instruction::SubtractI32);
+            self.instructions
+                .push(Instruction::BreakTable(labels, default));
+        } else {
+            for (label, (value, _)) in cases.iter().enumerate() {
+                self.instructions.push(Instruction::LocalGetI32(local));
+                self.instructions.push(Instruction::ConstI32(*value));
+                self.instructions.push(Instruction::EqualI32);
+                self.instructions.push(Instruction::BreakIf(label));
             }
-            Rule::string_literal => {
-                let value = pair.as_str();
-                let offset = self.strings.offset(value);
 
-                self.instructions.push(Instruction::ConstI32(offset));
+            self.instructions.push(Instruction::Break(default));
+        }
+    }
+
+    // Indexing goes through the prelude's bounds-checked accessors for the
+    // array's element type.
+    fn array_element(&self, array: &Expr) -> Element {
+        types::infer(array, self).map_or(Element::I32, Type::element)
+    }
+
+    // Memory intrinsics map to a single instruction taking and returning `i32`s.
+    fn collect_intrinsic(
+        &mut self,
+        function: &Identifier,
+        arguments: &[Expr],
+        parameters: usize,
+        instruction: Instruction,
+    ) {
+        if !self.check_arity(function, parameters, arguments.len()) {
+            self.instructions.push(Instruction::Unreachable);
+            return;
+        }
+
+        for argument in arguments {
+            self.collect_expression(argument, Type::I32);
+        }
+
+        self.instructions.push(instruction);
+    }
+
+    // `concat` takes any number of strings and joins them from left to right.
+    fn collect_concat(&mut self, function: &Identifier, arguments: &[Expr], span: Span) {
+        let Some((first, rest)) = arguments.split_first() else {
+            self.check_arity(function, 1, 0);
+            self.instructions.push(Instruction::Unreachable);
+            return;
+        };
+
+        self.collect_expression(first, Type::Array(Element::U8));
+
+        for argument in rest {
+            self.collect_expression(argument, Type::Array(Element::U8));
+            self.collect_runtime_call("strConcat", span);
+        }
+    }
+
+    // Arrays start with their length.
+    fn collect_len(&mut self, function: &Identifier, arguments: &[Expr]) {
+        if !self.check_arity(function, 1, arguments.len()) {
+            self.instructions.push(Instruction::Unreachable);
+            return;
+        }
+
+        self.collect_expression(&arguments[0], Type::Array(Element::I32));
+        self.instructions.push(load(Element::I32, 0));
+    }
+
+    // The right operand is only evaluated if the left one doesn't decide the
+    // result, and is normalized to 0 or 1 like the result of a comparison.
+    fn collect_logical(&mut self, operator: BinaryOperator, left: &Expr, right: &Expr) {
+        self.collect_expression(left, Type::I32);
+        self.enter(Instruction::If(Some(ValueType::I32)));
+
+        if operator == BinaryOperator::LogicalAnd {
+            self.collect_condition(right);
+            self.instructions.push(Instruction::Else);
+            self.instructions.push(Instruction::ConstI32(0));
+        } else {
+            self.instructions.push(Instruction::ConstI32(1));
+            self.instructions.push(Instruction::Else);
+            self.collect_condition(right);
+        }
+
+        self.exit();
+    }
+
+    fn collect_condition(&mut self, condition: &Expr) {
+        self.collect_expression(condition, Type::I32);
+
+        if !types::is_boolean(condition) {
+            self.instructions.push(Instruction::EqualZeroI32);
+            self.instructions.push(Instruction::EqualZeroI32);
+        }
+    }
+
+    fn collect_unary(&mut self, operator: UnaryOperator, operand: &Expr, expected: Type) {
+        let r#type = types::unary_operand(operator, operand, expected, self);
+
+        // WebAssembly only negates floats, so integers are subtracted from 0.
+        match (operator, r#type.value_type()) {
+            (UnaryOperator::Negate, ValueType::I32) => {
+                self.instructions.push(Instruction::ConstI32(0))
+            }
+            (UnaryOperator::Negate, ValueType::I64) => {
+                self.instructions.push(Instruction::ConstI64(0))
+            }
+            _ => (),
+        }
+
+        self.collect_expression(operand, r#type);
+
+        let instructions = match (operator, r#type.value_type()) {
+            (UnaryOperator::Negate, ValueType::I32) => vec![Instruction::SubtractI32],
+            (UnaryOperator::Negate, ValueType::I64) => vec![Instruction::SubtractI64],
+            (UnaryOperator::Negate, ValueType::F32) => vec![Instruction::NegateF32],
+            (UnaryOperator::Negate, ValueType::F64) => vec![Instruction::NegateF64],
+            (UnaryOperator::Not, ValueType::I32) => vec![Instruction::EqualZeroI32],
+            (UnaryOperator::Not, ValueType::I64) => vec![Instruction::EqualZeroI64],
+            (UnaryOperator::Complement, ValueType::I32) => {
+                vec![Instruction::ConstI32(-1), Instruction::XorI32]
+            }
+            (UnaryOperator::Complement, ValueType::I64) => {
+                vec![Instruction::ConstI64(-1), Instruction::XorI64]
             }
-            Rule::EOI => (),
-            _ => unreachable!("{:#?}", pair),
+            // Already reported by the checker.
+            (UnaryOperator::Not | UnaryOperator::Complement, ValueType::F32 | ValueType::F64) => {
+                vec![Instruction::Unreachable]
+            }
+        };
+
+        self.instructions.extend(instructions);
+    }
+
+    fn collect_conversion(&mut self, function: &Identifier, arguments: &[Expr]) {
+        if !self.check_arity(function, 1, arguments.len()) {
+            self.instructions.push(Instruction::Unreachable);
+            return;
+        }
+
+        let target = Type::from_name(&function.name).expect("conversions are named after types");
+        let source = types::conversion_source(&arguments[0], target, self);
+
+        self.collect_expression(&arguments[0], source);
+
+        if let Some(instruction) = conversion_instruction(source, target) {
+            self.instructions.push(instruction);
+        }
+    }
+
+    // Unresolved variables have already been reported while collecting symbols,
+    // and are replaced with `unreachable`, which satisfies any stack type.
+    fn collect_identifier(&mut self, identifier: &Identifier) {
+        self.instructions
+            .push(match self.symbols.variable(self.name, identifier) {
+                Some(Variable::Local(r#type, index)) => local_get(r#type, index),
+                Some(Variable::Global(_, index)) => Instruction::GlobalGet(index),
+                Some(Variable::Constant(value)) => value.instruction(),
+                None => Instruction::Unreachable,
+            });
+    }
+
+    fn collect_call(&mut self, index: usize, name: &str) {
+        if let Some(shadow) = &self.shadow {
+            self.instructions.extend(shadow.spill());
+        }
+
+        self.instructions.push(Instruction::Call(index));
+
+        if let Some(shadow) = &mut self.shadow
+            && self.symbols.result(name).map(Type::value_type) == Some(ValueType::I32)
+        {
+            self.instructions.extend(shadow.keep());
+        }
+    }
+
+    fn collect_epilogue(&mut self) {
+        if let Some(shadow) = &self.shadow {
+            self.instructions.extend(shadow.epilogue());
         }
     }
 
-    fn collect(&mut self, pair: Pair<Rule>) {
-        self.collect_inner(pair);
+    fn collect_runtime_call(&mut self, name: &str, span: Span) {
+        match self.symbols.function(name) {
+            Some(index) => self.collect_call(index, name),
+            None => self.errors.push(CompileError::UnknownFunction {
+                name: name.to_string(),
+                span,
+            }),
+        }
+    }
+
+    fn collect(&mut self, body: &Block) {
+        match self.result {
+            Some(result) => self.collect_block(body, result),
+            None => self.collect_void_block(body),
+        }
+
+        // The checker has made sure every path through a body without a
+        // trailing expression ends in `return`, but the validator can't see
+        // that after an `if` statement.
+        if body.result.is_none() && self.result.is_some() {
+            self.instructions.push(Instruction::Unreachable);
+        }
+
+        self.collect_epilogue();
         self.instructions.push(Instruction::End);
+
+        if let Some(shadow) = &self.shadow {
+            self.instructions.splice(0..0, shadow.prologue());
+        }
     }
 }
 
-fn parse_function(
-    pair: Pair<Rule>,
-    public: bool,
+fn value_types(types: Vec<Type>) -> Vec<ValueType> {
+    types.into_iter().map(Type::value_type).collect()
+}
+
+fn collect_function(
+    function: &ast::Function,
     symbols: &Symbols,
-    strings: &Strings,
+    data: &Data,
+    options: Options,
+    framed: bool,
+    errors: &mut Vec<CompileError>,
 ) -> Function {
-    let mut pairs = pair.into_inner();
-    let name = pairs.next().unwrap().as_str();
-    let _params = pairs.next().unwrap();
-    let body = pairs.next().unwrap();
+    let name = &function.name.name;
+    let mut locals = symbols.locals(name);
+
+    let mut collector = InstructionCollector::new(name, symbols, data, options);
 
-    let mut collector = InstructionCollector::new(name, symbols, strings);
-    collector.collect(body);
+    if framed {
+        collector.shadow = ShadowFrame::new(&symbols.parameters(name), &locals, symbols);
+    }
+
+    if let Some(shadow) = &collector.shadow {
+        locals.push(shadow.locals());
+    }
+
+    collector.collect(&function.body);
+
+    errors.extend(collector.errors);
 
     Function {
-        name: name.to_string(),
-        public,
-        parameters: symbols.parameters(name),
-        locals: symbols.locals(name),
+        name: name.clone(),
+        public: function.public,
+        parameters: value_types(symbols.parameters(name)),
+        returns: symbols
+            .result(name)
+            .map(Type::value_type)
+            .into_iter()
+            .collect(),
+        locals,
         instructions: collector.instructions,
     }
 }
 
 impl Wafer {
-    pub fn parse(input: &str) -> Self {
-        let parsed = Parser::parse(Rule::module, input)
-            .expect("failed to parse")
-            .next()
-            .unwrap();
-
-        let symbols = Symbols::from(parsed.clone());
-        let strings = Strings::from(parsed.clone());
-
-        let mut imports = vec![];
-        let mut functions = vec![];
-
-        for pair in parsed.into_inner() {
-            match pair.as_rule() {
-                Rule::external_function => {
-                    let name = pair.into_inner().next().unwrap().as_str();
-
-                    imports.push(Import {
-                        name: name.to_string(),
-                        parameters: symbols.parameters(name),
-                    });
+    #[cfg(test)]
+    pub fn parse(input: &str) -> Result<Self, Vec<CompileError>> {
+        let module = parse(input, Source::Input).map_err(|error| vec![error])?;
+
+        Self::from_ast(&module, Options::default())
+    }
+
+    pub fn from_ast(module: &Module, options: Options) -> Result<Self, Vec<CompileError>> {
+        let mut errors = vec![];
+
+        let symbols = Symbols::new(module, &mut errors);
+        let data = symbols.data();
+
+        let mut defined = HashSet::new();
+
+        let imports: Vec<_> = module
+            .imports
+            .iter()
+            .filter(|import| defined.insert(&import.name.name))
+            .map(|import| {
+                let name = &import.name.name;
+
+                Import {
+                    name: name.clone(),
+                    parameters: value_types(symbols.parameters(name)),
+                    returns: symbols
+                        .result(name)
+                        .map(Type::value_type)
+                        .into_iter()
+                        .collect(),
                 }
-                Rule::public_function => {
-                    functions.push(parse_function(
-                        pair.into_inner().next().unwrap(),
-                        true,
+            })
+            .collect();
+
+        // Duplicates have already been reported, and their bodies would be
+        // checked against the first definition's symbols.
+        let definitions: Vec<_> = module
+            .functions
+            .iter()
+            .filter(|function| defined.insert(&function.name.name))
+            .collect();
+
+        for function in &definitions {
+            checker::check_function(function, &symbols, &mut errors);
+        }
+
+        let mut functions: Vec<_> = definitions
+            .iter()
+            .map(|function| collect_function(function, &symbols, data, options, false, &mut errors))
+            .collect();
+
+        // Which functions need a shadow stack frame depends on what they call,
+        // so those are collected again with one.
+        if options.gc {
+            let framed = shadow::framed(&functions, imports.len(), &symbols);
+
+            for (index, framed) in framed.into_iter().enumerate() {
+                if framed {
+                    functions[index] = collect_function(
+                        definitions[index],
                         &symbols,
-                        &strings,
-                    ));
-                }
-                Rule::function => {
-                    functions.push(parse_function(pair, false, &symbols, &strings));
+                        data,
+                        options,
+                        true,
+                        &mut vec![],
+                    );
                 }
-                Rule::EOI => (),
-                _ => unreachable!(),
             }
         }
 
-        let data = strings.into_bytes();
+        if let Some(instructions) = shadow::mark_globals(&symbols) {
+            functions.push(Function {
+                name: MARK_GLOBALS.to_string(),
+                public: false,
+                parameters: vec![],
+                returns: vec![],
+                locals: vec![],
+                instructions,
+            });
+        }
 
-        Self {
+        let globals = symbols
+            .globals()
+            .map(|global| Global {
+                name: global.name.clone(),
+                public: global.public,
+                mutable: global.mutable,
+                r#type: global.r#type.value_type(),
+                init: global.value.instruction(),
+            })
+            .collect();
+
+        if !errors.is_empty() {
+            // Each pass reports its own errors, so they are put back into
+            // source order.
+            errors.sort_by_key(|error| {
+                let span = error.span();
+                (span.source, span.start)
+            });
+
+            return Err(errors);
+        }
+
+        Ok(Self {
             imports,
+            globals,
             functions,
-            data,
-        }
+            data: symbols.into_data().into_bytes(),
+        })
     }
 }
 
@@ -376,11 +1501,13 @@ impl Wafer {
 mod tests {
     use crate::wasm::{Instruction, ValueType};
 
-    use super::Wafer;
+    use super::ast::{Block, Expr, ExprKind, Function, Identifier, Module};
+    use super::types::Type;
+    use super::{CompileError, Options, Source, Span, Wafer, parse};
 
     #[test]
     fn should_parse_numbers() {
-        let wafer = Wafer::parse("func number() { 123 }");
+        let wafer = Wafer::parse("func number() { 123 }").unwrap();
         assert_eq!(wafer.functions.len(), 1);
 
         let function = &wafer.functions[0];
@@ -393,7 +1520,7 @@ mod tests {
 
     #[test]
     fn should_handle_let_statement() {
-        let wafer = Wafer::parse("func letstmt() { let x = 42; x * 2 }");
+        let wafer = Wafer::parse("func letstmt() { let x = 42; x * 2 }").unwrap();
         let function = &wafer.functions[0];
 
         assert_eq!(function.locals, vec![(1, ValueType::I32)]);
@@ -412,7 +1539,7 @@ mod tests {
 
     #[test]
     fn should_handle_expression_statement() {
-        let wafer = Wafer::parse("func exprstmt() { let x = 1; x := 2; 3 }");
+        let wafer = Wafer::parse("func exprstmt() { let x = 1; x := 2; 3 }").unwrap();
         let function = &wafer.functions[0];
 
         assert_eq!(
@@ -431,7 +1558,7 @@ mod tests {
 
     #[test]
     fn should_handle_multiple_functions() {
-        let wafer = Wafer::parse("func one() { 1 } func two() { 2 }");
+        let wafer = Wafer::parse("func one() { 1 } func two() { 2 }").unwrap();
 
         assert_eq!(wafer.functions.len(), 2);
         assert_eq!(wafer.functions[0].name, "one");
@@ -440,7 +1567,7 @@ mod tests {
 
     #[test]
     fn should_handle_function_with_parameters() {
-        let wafer = Wafer::parse("func withparams(x, y) { x + y }");
+        let wafer = Wafer::parse("func withparams(x, y) { x + y }").unwrap();
         let function = &wafer.functions[0];
 
         assert_eq!(function.parameters, vec![ValueType::I32, ValueType::I32]);
@@ -448,7 +1575,7 @@ mod tests {
 
     #[test]
     fn should_handle_function_call() {
-        let wafer = Wafer::parse("func one() { 1 } func caller() { one() + 2 }");
+        let wafer = Wafer::parse("func one() { 1 } func caller() { one() + 2 }").unwrap();
         let function = &wafer.functions[1];
 
         assert_eq!(
@@ -464,7 +1591,8 @@ mod tests {
 
     #[test]
     fn should_handle_function_call_with_parameters() {
-        let wafer = Wafer::parse("func add(x, y) { x + y } func caller() { add(3, 4 + 5) }");
+        let wafer =
+            Wafer::parse("func add(x, y) { x + y } func caller() { add(3, 4 + 5) }").unwrap();
         let function = &wafer.functions[1];
 
         assert_eq!(
@@ -480,69 +1608,298 @@ mod tests {
         );
     }
 
-    #[test]
-    fn should_handle_if_expression() {
-        let wafer = Wafer::parse("func iffy() { if 0 { 1 } else { 2 } }");
-        let function = &wafer.functions[0];
+    #[test]
+    fn should_handle_if_expression() {
+        let wafer = Wafer::parse("func iffy() { if 0 { 1 } else { 2 } }").unwrap();
+        let function = &wafer.functions[0];
+
+        assert_eq!(
+            function.instructions,
+            vec![
+                Instruction::ConstI32(0),
+                Instruction::If(Some(ValueType::I32)),
+                Instruction::ConstI32(1),
+                Instruction::Else,
+                Instruction::ConstI32(2),
+                Instruction::End,
+                Instruction::End
+            ]
+        );
+    }
+
+    #[test]
+    fn should_handle_if_statement() {
+        let wafer = Wafer::parse("func iffy() { if 0 { 1; } if 2 { 3; } else { 4; } 5 }").unwrap();
+        let function = &wafer.functions[0];
+
+        assert_eq!(
+            function.instructions,
+            vec![
+                Instruction::ConstI32(0),
+                Instruction::If(None),
+                Instruction::ConstI32(1),
+                Instruction::Drop,
+                Instruction::End,
+                Instruction::ConstI32(2),
+                Instruction::If(None),
+                Instruction::ConstI32(3),
+                Instruction::Drop,
+                Instruction::Else,
+                Instruction::ConstI32(4),
+                Instruction::Drop,
+                Instruction::End,
+                Instruction::ConstI32(5),
+                Instruction::End
+            ]
+        );
+    }
+
+    #[test]
+    fn should_handle_while() {
+        let wafer = Wafer::parse("func until() { while 0 { 1; } 2 }").unwrap();
+        let function = &wafer.functions[0];
+
+        assert_eq!(
+            function.instructions,
+            vec![
+                Instruction::Block(None),
+                Instruction::Loop(None),
+                Instruction::ConstI32(0),
+                Instruction::EqualZeroI32,
+                Instruction::BreakIf(1),
+                Instruction::ConstI32(1),
+                Instruction::Drop,
+                Instruction::Break(0),
+                Instruction::End,
+                Instruction::End,
+                Instruction::ConstI32(2),
+                Instruction::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_handle_break_and_continue() {
+        let wafer = Wafer::parse("func f(a) { while 1 { if a { break; } continue; } 0 }").unwrap();
+
+        assert_eq!(
+            wafer.functions[0].instructions,
+            vec![
+                Instruction::Block(None),
+                Instruction::Loop(None),
+                Instruction::ConstI32(1),
+                Instruction::EqualZeroI32,
+                Instruction::BreakIf(1),
+                Instruction::LocalGetI32(0),
+                Instruction::If(None),
+                Instruction::Break(2),
+                Instruction::End,
+                Instruction::Break(0),
+                Instruction::Break(0),
+                Instruction::End,
+                Instruction::End,
+                Instruction::ConstI32(0),
+                Instruction::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_compile_dense_matches_to_jump_tables() {
+        let wafer = Wafer::parse(
+            "enum E { A, B, C } func f(e: E) { match e { E::C => 1, E::A => 2, _ => 3 } }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            wafer.functions[0].instructions,
+            vec![
+                Instruction::Block(Some(ValueType::I32)),
+                Instruction::Block(None),
+                Instruction::Block(None),
+                Instruction::Block(None),
+                Instruction::LocalGetI32(0),
+                Instruction::LocalSetI32(1),
+                Instruction::LocalGetI32(1),
+                Instruction::ConstI32(0),
+                Instruction::SubtractI32,
+                Instruction::BreakTable(vec![1, 2, 0], 2),
+                Instruction::End,
+                Instruction::ConstI32(1),
+                Instruction::Break(2),
+                Instruction::End,
+                Instruction::ConstI32(2),
+                Instruction::Break(1),
+                Instruction::End,
+                Instruction::ConstI32(3),
+                Instruction::End,
+                Instruction::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_compare_sparse_matches() {
+        let wafer = Wafer::parse("func f(n) { match n { 100 => 1, 7 => 2, _ => 3 } }").unwrap();
+
+        assert_eq!(
+            wafer.functions[0].instructions,
+            vec![
+                Instruction::Block(Some(ValueType::I32)),
+                Instruction::Block(None),
+                Instruction::Block(None),
+                Instruction::Block(None),
+                Instruction::LocalGetI32(0),
+                Instruction::LocalSetI32(1),
+                Instruction::LocalGetI32(1),
+                Instruction::ConstI32(100),
+                Instruction::EqualI32,
+                Instruction::BreakIf(0),
+                Instruction::LocalGetI32(1),
+                Instruction::ConstI32(7),
+                Instruction::EqualI32,
+                Instruction::BreakIf(1),
+                Instruction::Break(2),
+                Instruction::End,
+                Instruction::ConstI32(1),
+                Instruction::Break(2),
+                Instruction::End,
+                Instruction::ConstI32(2),
+                Instruction::Break(1),
+                Instruction::End,
+                Instruction::ConstI32(3),
+                Instruction::End,
+                Instruction::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_access_globals_and_fold_constants() {
+        let wafer = Wafer::parse(
+            "const STEP: i64 = 2 * 3; public let count: i64 = STEP; func f() { count := count + STEP; 0 }",
+        )
+        .unwrap();
+
+        assert_eq!(wafer.globals.len(), 1);
+
+        let global = &wafer.globals[0];
+        assert_eq!(global.name, "count");
+        assert!(global.public);
+        assert!(global.mutable);
+        assert_eq!(global.r#type, ValueType::I64);
+        assert_eq!(global.init, Instruction::ConstI64(6));
 
         assert_eq!(
-            function.instructions,
+            wafer.functions[0].instructions,
             vec![
+                Instruction::GlobalGet(0),
+                Instruction::ConstI64(6),
+                Instruction::AddI64,
+                Instruction::GlobalSet(0),
+                Instruction::GlobalGet(0),
+                Instruction::Drop,
                 Instruction::ConstI32(0),
-                Instruction::If(Some(ValueType::I32)),
-                Instruction::ConstI32(1),
-                Instruction::Else,
-                Instruction::ConstI32(2),
                 Instruction::End,
-                Instruction::End
             ]
         );
     }
 
     #[test]
-    fn should_handle_if_statement() {
-        let wafer = Wafer::parse("func iffy() { if 0 { 1; } if 2 { 3; } else { 4; } 5 }");
+    fn should_reject_non_constant_initializers() {
+        let result = Wafer::parse("let a = 1; let b = a + 1; func f() { b }");
+
+        assert_eq!(
+            result.err(),
+            Some(vec![CompileError::NotConstant {
+                span: Span::new(19, 20),
+            }])
+        );
+    }
+
+    #[test]
+    fn should_handle_range_loops() {
+        let wafer = Wafer::parse("func f(n) { for i in 0..n { continue; } 0 }").unwrap();
         let function = &wafer.functions[0];
 
+        assert_eq!(function.locals, vec![(2, ValueType::I32)]);
         assert_eq!(
             function.instructions,
             vec![
                 Instruction::ConstI32(0),
-                Instruction::If(None),
+                Instruction::LocalSetI32(1),
+                Instruction::LocalGetI32(0),
+                Instruction::LocalSetI32(2),
+                Instruction::Block(None),
+                Instruction::Loop(None),
+                Instruction::LocalGetI32(1),
+                Instruction::LocalGetI32(2),
+                Instruction::LessThanSignedI32,
+                Instruction::EqualZeroI32,
+                Instruction::BreakIf(1),
+                Instruction::Block(None),
+                Instruction::Break(0),
+                Instruction::End,
+                Instruction::LocalGetI32(1),
                 Instruction::ConstI32(1),
-                Instruction::Drop,
+                Instruction::AddI32,
+                Instruction::LocalSetI32(1),
+                Instruction::Break(0),
                 Instruction::End,
-                Instruction::ConstI32(2),
-                Instruction::If(None),
-                Instruction::ConstI32(3),
-                Instruction::Drop,
-                Instruction::Else,
-                Instruction::ConstI32(4),
-                Instruction::Drop,
                 Instruction::End,
-                Instruction::ConstI32(5),
-                Instruction::End
+                Instruction::ConstI32(0),
+                Instruction::End,
             ]
         );
     }
 
     #[test]
-    fn should_handle_while() {
-        let wafer = Wafer::parse("func until() { while 0 { 1; } 2 }");
-        let function = &wafer.functions[0];
+    fn should_scope_loop_variables_to_the_body() {
+        let result = Wafer::parse("func f(a) { for x in a { x; } x }");
 
         assert_eq!(
-            function.instructions,
+            result.err(),
+            Some(vec![CompileError::OutOfScope {
+                name: "x".to_string(),
+                span: Span::new(30, 31),
+            }])
+        );
+    }
+
+    #[test]
+    fn should_reject_break_outside_a_loop() {
+        let result = Wafer::parse("func f(a) { if a { break; } 0 }");
+
+        assert_eq!(
+            result.err(),
+            Some(vec![CompileError::OutsideLoop {
+                keyword: "break".to_string(),
+                span: Span::new(19, 25),
+            }])
+        );
+    }
+
+    #[test]
+    fn should_handle_void_functions_and_returns() {
+        let wafer = Wafer::parse(
+            "extern func log(a); func f(a): i32 { if a { return 1; } log(a); return 0; }",
+        )
+        .unwrap();
+
+        assert_eq!(wafer.imports[0].returns, vec![]);
+        assert_eq!(
+            wafer.functions[0].instructions,
             vec![
-                Instruction::Loop(None),
-                Instruction::ConstI32(0),
+                Instruction::LocalGetI32(0),
                 Instruction::If(None),
                 Instruction::ConstI32(1),
-                Instruction::Drop,
-                Instruction::Break(1),
+                Instruction::Return,
                 Instruction::End,
-                Instruction::End,
-                Instruction::ConstI32(2),
+                Instruction::LocalGetI32(0),
+                Instruction::Call(0),
+                Instruction::ConstI32(0),
+                Instruction::Return,
+                Instruction::Unreachable,
                 Instruction::End,
             ]
         );
@@ -550,7 +1907,7 @@ mod tests {
 
     #[test]
     fn should_handle_imports() {
-        let wafer = Wafer::parse("extern func add(a, b);");
+        let wafer = Wafer::parse("extern func add(a, b);").unwrap();
         let import = &wafer.imports[0];
 
         assert_eq!(import.name, "add");
@@ -559,7 +1916,7 @@ mod tests {
 
     #[test]
     fn should_handle_memory_operations() {
-        let wafer = Wafer::parse("func memory() { __mem[1] := 2; __mem[3] }");
+        let wafer = Wafer::parse("func memory() { __mem[1] := 2; __mem[3] }").unwrap();
         let function = &wafer.functions[0];
 
         assert_eq!(
@@ -578,6 +1935,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn should_handle_narrow_memory_operations() {
+        let wafer = Wafer::parse("func memory() { __mem8[1] := 2; __mem16[4] }").unwrap();
+        let function = &wafer.functions[0];
+
+        assert_eq!(
+            function.instructions,
+            vec![
+                Instruction::ConstI32(1),
+                Instruction::ConstI32(2),
+                Instruction::LocalTeeI32(0),
+                Instruction::Store8I32(0, 0),
+                Instruction::LocalGetI32(0),
+                Instruction::Drop,
+                Instruction::ConstI32(4),
+                Instruction::Load16UnsignedI32(1, 0),
+                Instruction::End
+            ]
+        );
+    }
+
     #[test]
     fn should_handle_array_operations() {
         let wafer = Wafer::parse(
@@ -595,7 +1973,8 @@ mod tests {
                     x[1] := 2;
                     x[3]
                 }",
-        );
+        )
+        .unwrap();
         let function = &wafer.functions[2];
 
         assert_eq!(
@@ -630,7 +2009,8 @@ mod tests {
                 0
             }
         "#,
-        );
+        )
+        .unwrap();
         let function = &wafer.functions[1];
 
         assert_eq!(
@@ -638,7 +2018,7 @@ mod tests {
             vec![
                 Instruction::ConstI32(0),
                 Instruction::LocalSetI32(0),
-                Instruction::ConstI32(24),
+                Instruction::ConstI32(12),
                 Instruction::LocalSetI32(1),
                 Instruction::ConstI32(0),
                 Instruction::End
@@ -653,9 +2033,422 @@ mod tests {
                 func a() { 0 }
                 public func b() { 0 }
             ",
-        );
+        )
+        .unwrap();
 
         assert!(!wafer.functions[0].public);
         assert!(wafer.functions[1].public);
     }
+
+    #[test]
+    fn should_compile_ast_built_directly() {
+        let span = Span::new(0, 0);
+        let module = Module {
+            imports: vec![],
+            structs: vec![],
+            enums: vec![],
+            globals: vec![],
+            functions: vec![Function {
+                name: Identifier {
+                    name: "answer".to_string(),
+                    span,
+                },
+                public: true,
+                parameters: vec![],
+                returns: None,
+                body: Block {
+                    statements: vec![],
+                    result: Some(Box::new(Expr {
+                        kind: ExprKind::Number(42),
+                        span,
+                    })),
+                    span,
+                },
+            }],
+        };
+
+        let wafer = Wafer::from_ast(&module, Options::default()).unwrap();
+        let function = &wafer.functions[0];
+
+        assert!(function.public);
+        assert_eq!(
+            function.instructions,
+            vec![Instruction::ConstI32(42), Instruction::End]
+        );
+    }
+
+    #[test]
+    fn should_handle_i64_arithmetic() {
+        let wafer = Wafer::parse("func wide(a: i64): i64 { let b = a * 3; b - 1 }").unwrap();
+        let function = &wafer.functions[0];
+
+        assert_eq!(function.parameters, vec![ValueType::I64]);
+        assert_eq!(function.returns, vec![ValueType::I64]);
+        assert_eq!(function.locals, vec![(1, ValueType::I64)]);
+        assert_eq!(
+            function.instructions,
+            vec![
+                Instruction::LocalGetI64(0),
+                Instruction::ConstI64(3),
+                Instruction::MultiplyI64,
+                Instruction::LocalSetI64(1),
+                Instruction::LocalGetI64(1),
+                Instruction::ConstI64(1),
+                Instruction::SubtractI64,
+                Instruction::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_handle_unary_operators() {
+        let wafer = Wafer::parse("func f(a: i64, x: f32): i32 { !(-a) + ~i32(-x) }").unwrap();
+
+        assert_eq!(
+            wafer.functions[0].instructions,
+            vec![
+                Instruction::ConstI64(0),
+                Instruction::LocalGetI64(0),
+                Instruction::SubtractI64,
+                Instruction::EqualZeroI64,
+                Instruction::LocalGetF32(1),
+                Instruction::NegateF32,
+                Instruction::TruncateSignedF32ToI32,
+                Instruction::ConstI32(-1),
+                Instruction::XorI32,
+                Instruction::AddI32,
+                Instruction::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_short_circuit_logical_operators() {
+        let wafer = Wafer::parse("func f(a, b): i32 { a && b || a < b }").unwrap();
+
+        assert_eq!(
+            wafer.functions[0].instructions,
+            vec![
+                Instruction::LocalGetI32(0),
+                Instruction::If(Some(ValueType::I32)),
+                Instruction::LocalGetI32(1),
+                Instruction::EqualZeroI32,
+                Instruction::EqualZeroI32,
+                Instruction::Else,
+                Instruction::ConstI32(0),
+                Instruction::End,
+                Instruction::If(Some(ValueType::I32)),
+                Instruction::ConstI32(1),
+                Instruction::Else,
+                Instruction::LocalGetI32(0),
+                Instruction::LocalGetI32(1),
+                Instruction::LessThanSignedI32,
+                Instruction::End,
+                Instruction::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_handle_integer_conversions() {
+        let wafer = Wafer::parse("func narrow(a: i64) { i32(a) + i32(i64(2)) }").unwrap();
+
+        assert_eq!(
+            wafer.functions[0].instructions,
+            vec![
+                Instruction::LocalGetI64(0),
+                Instruction::WrapI64,
+                Instruction::ConstI64(2),
+                Instruction::WrapI64,
+                Instruction::AddI32,
+                Instruction::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_handle_float_arithmetic() {
+        let wafer = Wafer::parse("func scale(x: f32): f64 { f64(x * 2) + 0.5 }").unwrap();
+        let function = &wafer.functions[0];
+
+        assert_eq!(function.parameters, vec![ValueType::F32]);
+        assert_eq!(function.returns, vec![ValueType::F64]);
+        assert_eq!(
+            function.instructions,
+            vec![
+                Instruction::LocalGetF32(0),
+                Instruction::ConstF32(2.0),
+                Instruction::MultiplyF32,
+                Instruction::PromoteF32,
+                Instruction::ConstF64(0.5),
+                Instruction::AddF64,
+                Instruction::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_type_typed_imports() {
+        let wafer = Wafer::parse("extern func sin(x: f64): f64;").unwrap();
+
+        assert_eq!(wafer.imports[0].parameters, vec![ValueType::F64]);
+        assert_eq!(wafer.imports[0].returns, vec![ValueType::F64]);
+    }
+
+    #[test]
+    fn should_reject_float_literals_in_integer_context() {
+        let result = Wafer::parse("func whole(a) { a + 1.5 }");
+
+        assert_eq!(
+            result.err(),
+            Some(vec![CompileError::MismatchedTypes {
+                expected: "i32".to_string(),
+                found: "f64".to_string(),
+                span: Span::new(20, 23),
+            }])
+        );
+    }
+
+    #[test]
+    fn should_select_unsigned_instructions() {
+        let wafer =
+            Wafer::parse("func f(a: u32, b: u64): i32 { i32(a / 3 > 0xFF) + i32(f64(b >> 1)) }")
+                .unwrap();
+
+        assert_eq!(
+            wafer.functions[0].instructions,
+            vec![
+                Instruction::LocalGetI32(0),
+                Instruction::ConstI32(3),
+                Instruction::DivideUnsignedI32,
+                Instruction::ConstI32(255),
+                Instruction::GreaterThanUnsignedI32,
+                Instruction::LocalGetI64(1),
+                Instruction::ConstI64(1),
+                Instruction::ShiftRightUnsignedI64,
+                Instruction::ConvertUnsignedI64ToF64,
+                Instruction::TruncateSignedF64ToI32,
+                Instruction::AddI32,
+                Instruction::End,
+            ]
+        );
+    }
+
+    fn optimize(input: &str, opt_level: u8) -> Vec<Instruction> {
+        let module = parse(input, Source::Input).unwrap();
+        let options = Options {
+            opt_level,
+            ..Options::default()
+        };
+        let mut wafer = Wafer::from_ast(&module, options).unwrap();
+
+        wafer.functions.remove(0).instructions
+    }
+
+    #[test]
+    fn should_only_give_frames_to_functions_that_may_collect() {
+        let input = r"
+            let __gcStackTop = 0;
+
+            func __gcEnter(size) { size }
+            func __gcCollect() { }
+            func alloc() { __gcCollect(); 8 }
+            func make() { alloc() }
+            func pure(a) { a + 1 }
+        ";
+        let module = parse(input, Source::Input).unwrap();
+        let options = Options {
+            gc: true,
+            ..Options::default()
+        };
+        let wafer = Wafer::from_ast(&module, options).unwrap();
+
+        let framed: Vec<_> = wafer
+            .functions
+            .iter()
+            .filter(|function| function.instructions.contains(&Instruction::Call(0)))
+            .map(|function| function.name.as_str())
+            .collect();
+
+        assert_eq!(framed, vec!["alloc", "make"]);
+    }
+
+    #[test]
+    fn should_fold_constant_subexpressions() {
+        let input = "const N = 4; func f(a): i32 { a + (N * 3 - 2) + (1 / 0) }";
+
+        assert_eq!(
+            optimize(input, 1),
+            vec![
+                Instruction::LocalGetI32(0),
+                Instruction::ConstI32(10),
+                Instruction::AddI32,
+                Instruction::ConstI32(1),
+                Instruction::ConstI32(0),
+                Instruction::DivideSignedI32,
+                Instruction::AddI32,
+                Instruction::End,
+            ]
+        );
+        assert_eq!(
+            optimize("func f(): f64 { -(1.5 * 2) }", 1),
+            vec![Instruction::ConstF64(-3.0), Instruction::End]
+        );
+    }
+
+    #[test]
+    fn should_simplify_arithmetic_identities() {
+        let input = "func f(a: i64, b: u32): i64 { (0 + a * 1) * 8 + i64(b * 0x80000000 - 0) }";
+
+        assert_eq!(
+            optimize(input, 1)[..3],
+            [
+                Instruction::ConstI64(0),
+                Instruction::LocalGetI64(0),
+                Instruction::ConstI64(1),
+            ]
+        );
+        assert_eq!(
+            optimize(input, 2),
+            vec![
+                Instruction::LocalGetI64(0),
+                Instruction::ConstI64(3),
+                Instruction::ShiftLeftI64,
+                Instruction::LocalGetI32(1),
+                Instruction::ConstI32(31),
+                Instruction::ShiftLeftI32,
+                Instruction::ExtendUnsignedI32,
+                Instruction::AddI64,
+                Instruction::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_remove_dead_branches() {
+        let input = "func f(a): i32 { if 2 > 3 { a := 1; } if 1 { a } else { 0 } }";
+
+        assert_eq!(
+            optimize(input, 2),
+            vec![Instruction::LocalGetI32(0), Instruction::End]
+        );
+        assert!(matches!(
+            Wafer::from_ast(
+                &parse("func f() { if 0 { g(); } }", Source::Input).unwrap(),
+                Options {
+                    opt_level: 2,
+                    ..Options::default()
+                }
+            )
+            .err()
+            .as_deref(),
+            Some([CompileError::UnknownFunction { .. }])
+        ));
+    }
+
+    #[test]
+    fn should_reject_out_of_range_unsigned_literals() {
+        let result = Wafer::parse("func f() { let a: u32 = 0x100000000; let b: u64 = -1; }");
+
+        assert_eq!(
+            result.err(),
+            Some(vec![
+                CompileError::NumberOutOfRange {
+                    value: "4294967296".to_string(),
+                    r#type: Type::U32,
+                    span: Span::new(24, 35),
+                },
+                CompileError::NumberOutOfRange {
+                    value: "-1".to_string(),
+                    r#type: Type::U64,
+                    span: Span::new(50, 52),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn should_reject_out_of_range_i32_literals() {
+        let result = Wafer::parse("func big() { 3000000000 }");
+
+        assert_eq!(
+            result.err(),
+            Some(vec![CompileError::NumberOutOfRange {
+                value: "3000000000".to_string(),
+                r#type: Type::I32,
+                span: Span::new(13, 23),
+            }])
+        );
+    }
+
+    #[test]
+    fn should_reject_syntax_errors() {
+        let errors = Wafer::parse("func broken() { 1 + }").err().unwrap();
+
+        assert!(matches!(errors[..], [CompileError::Syntax { .. }]));
+    }
+
+    #[test]
+    fn should_reject_unknown_variables() {
+        let result = Wafer::parse("func unknown() { x + 1 }");
+
+        assert_eq!(
+            result.err(),
+            Some(vec![CompileError::UnknownVariable {
+                name: "x".to_string(),
+                span: Span::new(17, 18),
+            }])
+        );
+    }
+
+    #[test]
+    fn should_reject_unknown_functions() {
+        let result = Wafer::parse("func caller() { missing(1) }");
+
+        assert_eq!(
+            result.err(),
+            Some(vec![CompileError::UnknownFunction {
+                name: "missing".to_string(),
+                span: Span::new(16, 23),
+            }])
+        );
+    }
+
+    #[test]
+    fn should_reject_arity_mismatches() {
+        let result = Wafer::parse("func add(x, y) { x + y } func caller() { add(1) }");
+
+        assert_eq!(
+            result.err(),
+            Some(vec![CompileError::ArityMismatch {
+                name: "add".to_string(),
+                expected: 2,
+                found: 1,
+                span: Span::new(41, 44),
+            }])
+        );
+    }
+
+    #[test]
+    fn should_report_all_errors_in_a_module() {
+        let result = Wafer::parse(
+            r"
+                func first(a) { b + missing(a) }
+                func second() { first() + c }
+                func first() { 0 }
+            ",
+        );
+
+        let errors = result.err().unwrap();
+
+        assert_eq!(errors.len(), 5);
+        assert!(matches!(errors[0], CompileError::UnknownVariable { .. }));
+        assert!(matches!(errors[1], CompileError::UnknownFunction { .. }));
+        assert!(matches!(errors[2], CompileError::ArityMismatch { .. }));
+        assert!(matches!(errors[3], CompileError::UnknownVariable { .. }));
+        assert!(matches!(
+            errors[4],
+            CompileError::DuplicateDefinition { .. }
+        ));
+    }
 }
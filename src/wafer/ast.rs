@@ -0,0 +1,385 @@
+use super::Span;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Identifier {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
+    Remainder,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+    ShiftRightUnsigned,
+    LogicalAnd,
+    LogicalOr,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+    Complement,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExprKind {
+    Number(i128),
+    Float(f64),
+    String(String),
+    Variable(Identifier),
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expr>,
+    },
+    Binary {
+        operator: BinaryOperator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Call {
+        function: Identifier,
+        arguments: Vec<Expr>,
+    },
+    Index {
+        array: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Assign {
+        target: Identifier,
+        value: Box<Expr>,
+    },
+    IndexAssign {
+        array: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Field {
+        object: Box<Expr>,
+        field: Identifier,
+    },
+    FieldAssign {
+        object: Box<Expr>,
+        field: Identifier,
+        value: Box<Expr>,
+    },
+    Struct {
+        name: Identifier,
+        fields: Vec<(Identifier, Expr)>,
+    },
+    Array(Vec<Expr>),
+    Variant {
+        r#enum: Identifier,
+        variant: Identifier,
+    },
+    Match {
+        value: Box<Expr>,
+        arms: Vec<Arm>,
+    },
+    If {
+        condition: Box<Expr>,
+        then_block: Block,
+        else_block: Block,
+    },
+}
+
+// Patterns are compared against the matched value, and must be constants.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    Value(Expr),
+    Wildcard(Span),
+}
+
+// Arms without a block are represented as a block holding only their value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub body: Block,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StmtKind {
+    Let {
+        name: Identifier,
+        r#type: Option<Identifier>,
+        value: Expr,
+    },
+    If {
+        condition: Expr,
+        then_block: Block,
+        else_block: Option<Block>,
+    },
+    While {
+        condition: Expr,
+        body: Block,
+    },
+    For {
+        variable: Identifier,
+        iterable: Iterable,
+        body: Block,
+    },
+    Return(Option<Expr>),
+    Break,
+    Continue,
+    Expr(Expr),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Iterable {
+    Range { start: Expr, end: Expr },
+    Array(Expr),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+// `else if` chains are represented as an else block holding only the nested
+// `if`, so code generation never needs to special-case them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub statements: Vec<Stmt>,
+    pub result: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Parameter {
+    pub name: Identifier,
+    pub r#type: Option<Identifier>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: Identifier,
+    pub public: bool,
+    pub parameters: Vec<Parameter>,
+    pub returns: Option<Identifier>,
+    pub body: Block,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Import {
+    pub name: Identifier,
+    pub parameters: Vec<Parameter>,
+    pub returns: Option<Identifier>,
+}
+
+// A module-level `let` or `const`. Constants are folded into their uses and
+// only become WebAssembly globals when they are exported.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Global {
+    pub name: Identifier,
+    pub public: bool,
+    pub constant: bool,
+    pub r#type: Option<Identifier>,
+    pub value: Expr,
+}
+
+// Fields are declared like parameters, and are `i32`s unless annotated.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Struct {
+    pub name: Identifier,
+    pub fields: Vec<Parameter>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Variant {
+    pub name: Identifier,
+    pub discriminant: Option<Expr>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Enum {
+    pub name: Identifier,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Module {
+    pub imports: Vec<Import>,
+    pub structs: Vec<Struct>,
+    pub enums: Vec<Enum>,
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    pub fn merge(mut self, other: Module) -> Self {
+        self.imports.extend(other.imports);
+        self.structs.extend(other.structs);
+        self.enums.extend(other.enums);
+        self.globals.extend(other.globals);
+        self.functions.extend(other.functions);
+
+        self
+    }
+
+    // Functions of the other module replace the ones with the same name, so
+    // that the collector's runtime can swap in its own `alloc`.
+    pub fn layer(mut self, other: Module) -> Self {
+        self.functions.retain(|function| {
+            !other
+                .functions
+                .iter()
+                .any(|replacement| replacement.name.name == function.name.name)
+        });
+
+        self.merge(other)
+    }
+}
+
+pub trait Visitor {
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &Block) {
+    for stmt in &block.statements {
+        visitor.visit_stmt(stmt);
+    }
+
+    if let Some(result) = &block.result {
+        visitor.visit_expr(result);
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match &stmt.kind {
+        StmtKind::Let { value, .. } => visitor.visit_expr(value),
+        StmtKind::If {
+            condition,
+            then_block,
+            else_block,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(then_block);
+
+            if let Some(else_block) = else_block {
+                visitor.visit_block(else_block);
+            }
+        }
+        StmtKind::While { condition, body } => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(body);
+        }
+        StmtKind::For { iterable, body, .. } => {
+            match iterable {
+                Iterable::Range { start, end } => {
+                    visitor.visit_expr(start);
+                    visitor.visit_expr(end);
+                }
+                Iterable::Array(array) => visitor.visit_expr(array),
+            }
+
+            visitor.visit_block(body);
+        }
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        StmtKind::Break | StmtKind::Continue => (),
+        StmtKind::Expr(expr) => visitor.visit_expr(expr),
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match &expr.kind {
+        ExprKind::Number(_)
+        | ExprKind::Float(_)
+        | ExprKind::String(_)
+        | ExprKind::Variable(_)
+        | ExprKind::Variant { .. } => {}
+        ExprKind::Unary { operand, .. } => visitor.visit_expr(operand),
+        ExprKind::Binary { left, right, .. } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        ExprKind::Call { arguments, .. } => {
+            for argument in arguments {
+                visitor.visit_expr(argument);
+            }
+        }
+        ExprKind::Index { array, index } => {
+            visitor.visit_expr(array);
+            visitor.visit_expr(index);
+        }
+        ExprKind::Assign { value, .. } => visitor.visit_expr(value),
+        ExprKind::IndexAssign {
+            array,
+            index,
+            value,
+        } => {
+            visitor.visit_expr(array);
+            visitor.visit_expr(index);
+            visitor.visit_expr(value);
+        }
+        ExprKind::Field { object, .. } => visitor.visit_expr(object),
+        ExprKind::FieldAssign { object, value, .. } => {
+            visitor.visit_expr(object);
+            visitor.visit_expr(value);
+        }
+        ExprKind::Struct { fields, .. } => {
+            for (_, value) in fields {
+                visitor.visit_expr(value);
+            }
+        }
+        ExprKind::Array(elements) => {
+            for element in elements {
+                visitor.visit_expr(element);
+            }
+        }
+        ExprKind::Match { value, arms } => {
+            visitor.visit_expr(value);
+
+            for arm in arms {
+                if let Pattern::Value(pattern) = &arm.pattern {
+                    visitor.visit_expr(pattern);
+                }
+
+                visitor.visit_block(&arm.body);
+            }
+        }
+        ExprKind::If {
+            condition,
+            then_block,
+            else_block,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(then_block);
+            visitor.visit_block(else_block);
+        }
+    }
+}
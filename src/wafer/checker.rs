@@ -0,0 +1,901 @@
+use std::collections::HashSet;
+
+use super::ast::{
+    Arm, Block, Expr, ExprKind, Function, Identifier, Iterable, Pattern, Stmt, StmtKind,
+    UnaryOperator,
+};
+use super::constants::Value;
+use super::symbols::{Symbols, Variable};
+use super::types::{self, Element, Environment, Type, TypeNames};
+use super::{CompileError, Span};
+
+// Checks a function body against the types resolved by `Symbols`. Every
+// expression is checked against the type its context expects, using the same
+// inference as code generation, so a module that passes is always valid
+// WebAssembly. Unknown variables are reported while resolving `Symbols` and
+// unknown functions by code generation.
+struct Checker<'a> {
+    name: &'a str,
+    result: Option<Type>,
+    symbols: &'a Symbols,
+    errors: &'a mut Vec<CompileError>,
+}
+
+impl Environment for Checker<'_> {
+    fn variable(&self, identifier: &Identifier) -> Option<Type> {
+        self.symbols.variable_type(self.name, identifier)
+    }
+
+    fn result(&self, function: &str) -> Option<Type> {
+        self.symbols.result(function)
+    }
+
+    fn field(&self, r#type: Type, field: &str) -> Option<Type> {
+        self.symbols.field(r#type, field).map(|field| field.r#type)
+    }
+
+    fn type_names(&self) -> &TypeNames {
+        self.symbols.type_names()
+    }
+}
+
+impl Checker<'_> {
+    fn describe(&self, r#type: Type) -> String {
+        self.symbols.type_names().describe(r#type)
+    }
+
+    fn unify(&mut self, expected: Type, found: Option<Type>, span: Span) {
+        if let Some(found) = found
+            && !expected.accepts(found)
+        {
+            self.errors.push(CompileError::mismatched_types(
+                expected,
+                found,
+                span,
+                self.symbols.type_names(),
+            ));
+        }
+    }
+
+    fn check_arguments(&mut self, name: &str, arguments: &[Expr]) {
+        for (argument, r#type) in arguments.iter().zip(self.symbols.parameters(name)) {
+            self.check_expression(argument, r#type);
+        }
+    }
+
+    // The memory intrinsics can be indexed without being variables. Any array
+    // can be indexed, whatever its element type.
+    fn check_array(&mut self, array: &Expr) {
+        if Element::from_memory(array).is_none() {
+            let expected = match types::infer(array, self) {
+                Some(r#type @ Type::Array(_)) => r#type,
+                _ => Type::Array(Element::I32),
+            };
+
+            self.check_expression(array, expected);
+        }
+    }
+
+    // Returns the field's type, if the object has such a field. Unknown
+    // variables have already been reported.
+    fn check_field(&mut self, object: &Expr, field: &Identifier) -> Option<Type> {
+        let object_type = types::infer(object, self)?;
+        self.check_expression(object, object_type);
+
+        let found = self.field(object_type, &field.name);
+
+        if found.is_none() {
+            self.errors.push(CompileError::UnknownField {
+                name: field.name.clone(),
+                r#type: self.describe(object_type),
+                span: field.span,
+            });
+        }
+
+        found
+    }
+
+    fn check_struct(&mut self, name: &Identifier, fields: &[(Identifier, Expr)]) -> Option<Type> {
+        let r#type = self.symbols.type_name(&name.name).map(Type::Struct);
+
+        let Some(symbol) = r#type.and_then(|r#type| self.symbols.r#struct(r#type)) else {
+            self.errors.push(CompileError::unknown_type(
+                &name.name,
+                name.span,
+                self.symbols.type_names(),
+            ));
+
+            return None;
+        };
+
+        let r#type = Type::Struct(symbol.name);
+        let mut initialized = HashSet::new();
+
+        for (field, value) in fields {
+            if !initialized.insert(&field.name) {
+                self.errors.push(CompileError::DuplicateDefinition {
+                    name: field.name.clone(),
+                    span: field.span,
+                });
+            }
+
+            match self.field(r#type, &field.name) {
+                Some(field_type) => self.check_expression(value, field_type),
+                None => self.errors.push(CompileError::UnknownField {
+                    name: field.name.clone(),
+                    r#type: self.describe(r#type),
+                    span: field.span,
+                }),
+            }
+        }
+
+        for field in &symbol.fields {
+            if !initialized.contains(&field.name) {
+                self.errors.push(CompileError::MissingField {
+                    name: field.name.clone(),
+                    r#type: self.describe(r#type),
+                    span: name.span,
+                });
+            }
+        }
+
+        Some(r#type)
+    }
+
+    fn check_variant(&mut self, r#enum: &Identifier, variant: &Identifier) -> Option<Type> {
+        let Some(r#type) = self
+            .symbols
+            .type_name(&r#enum.name)
+            .map(Type::Enum)
+            .filter(|&r#type| self.symbols.r#enum(r#type).is_some())
+        else {
+            self.errors.push(CompileError::unknown_type(
+                &r#enum.name,
+                r#enum.span,
+                self.symbols.type_names(),
+            ));
+
+            return None;
+        };
+
+        if self.symbols.variant(r#type, &variant.name).is_none() {
+            self.errors.push(CompileError::UnknownVariant {
+                name: variant.name.clone(),
+                r#type: self.describe(r#type),
+                span: variant.span,
+            });
+        }
+
+        Some(r#type)
+    }
+
+    // Matched values are `i32`s. Without a `_` arm, matches on enums need an
+    // arm for every variant, and matches on numbers can't be exhaustive. The
+    // first arm for a value wins, so later ones, and any after a `_`, are
+    // reported. Matches in statement position have no `expected` type, and
+    // their arms are checked like void bodies.
+    fn check_match(&mut self, value: &Expr, arms: &[Arm], expected: Option<Type>) {
+        let r#type = types::infer(value, self).unwrap_or(Type::I32);
+        self.check_expression(value, r#type);
+        self.unify(Type::I32, Some(r#type), value.span);
+
+        let mut covered = HashSet::new();
+        let mut wildcard = false;
+
+        for arm in arms {
+            match &arm.pattern {
+                Pattern::Wildcard(span) => {
+                    if wildcard {
+                        self.errors
+                            .push(CompileError::UnreachablePattern { span: *span });
+                    }
+
+                    wildcard = true;
+                }
+                Pattern::Value(pattern) => {
+                    self.check_expression(pattern, Type::I32);
+
+                    // Variants of one enum can't stand in for another's.
+                    if let (Type::Enum(_), Some(found @ Type::Enum(_))) =
+                        (r#type, types::infer(pattern, self))
+                        && found != r#type
+                    {
+                        self.unify(r#type, Some(found), pattern.span);
+                    }
+
+                    let reachable = match self.symbols.pattern(pattern) {
+                        Some(value) => covered.insert(value),
+                        None => true,
+                    };
+
+                    if wildcard || !reachable {
+                        self.errors
+                            .push(CompileError::UnreachablePattern { span: pattern.span });
+                    }
+                }
+            }
+
+            match expected {
+                Some(expected) => self.check_block(&arm.body, expected),
+                None => self.check_void_block(&arm.body),
+            }
+        }
+
+        if wildcard {
+            return;
+        }
+
+        let missing: Vec<_> = match self.symbols.r#enum(r#type) {
+            Some(symbol) => symbol
+                .variants
+                .iter()
+                .filter(|variant| !covered.contains(&variant.discriminant))
+                .map(|variant| format!("{}::{}", self.describe(r#type), variant.name))
+                .collect(),
+            None => vec!["_".to_string()],
+        };
+
+        if !missing.is_empty() {
+            self.errors.push(CompileError::NonExhaustiveMatch {
+                missing,
+                span: value.span,
+            });
+        }
+    }
+
+    fn check_block(&mut self, block: &Block, expected: Type) {
+        for stmt in &block.statements {
+            self.check_statement(stmt);
+        }
+
+        if let Some(result) = &block.result {
+            self.check_expression(result, expected);
+        }
+    }
+
+    fn check_statement(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let { name, value, .. } => {
+                if let Some(r#type) = self.variable(name) {
+                    self.check_expression(value, r#type);
+                }
+            }
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                self.check_expression(condition, Type::I32);
+                self.check_block(then_block, Type::I32);
+
+                if let Some(else_block) = else_block {
+                    self.check_block(else_block, Type::I32);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.check_expression(condition, Type::I32);
+                self.check_block(body, Type::I32);
+            }
+            StmtKind::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                match iterable {
+                    Iterable::Range { start, end } => {
+                        if let Some(r#type) = self.variable(variable) {
+                            self.check_expression(start, r#type);
+                            self.check_expression(end, r#type);
+
+                            if r#type.is_float() {
+                                self.unify(Type::I32, Some(r#type), start.span);
+                            }
+                        }
+                    }
+                    Iterable::Array(array) => self.check_expression(array, Type::I32),
+                }
+
+                self.check_block(body, Type::I32);
+            }
+            StmtKind::Return(value) => match (value, self.result) {
+                (Some(value), Some(result)) => self.check_expression(value, result),
+                (Some(value), None) => self
+                    .errors
+                    .push(CompileError::UnexpectedValue { span: value.span }),
+                (None, Some(result)) => self.errors.push(CompileError::MissingValue {
+                    expected: self.describe(result),
+                    span: stmt.span,
+                }),
+                (None, None) => (),
+            },
+            StmtKind::Break | StmtKind::Continue => (),
+            StmtKind::Expr(expr) => self.check_expression_statement(expr),
+        }
+    }
+
+    fn check_expression_statement(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Match { value, arms } => self.check_match(value, arms, None),
+            ExprKind::Call {
+                function,
+                arguments,
+            } if self.symbols.is_void(&function.name) => {
+                self.check_arguments(&function.name, arguments);
+            }
+            _ => {
+                let r#type = types::infer(expr, self).unwrap_or_else(|| types::default_type(expr));
+                self.check_expression(expr, r#type);
+            }
+        }
+    }
+
+    fn check_body(&mut self, body: &Block) {
+        match self.result {
+            Some(result) => self.check_block(body, result),
+            None => self.check_void_block(body),
+        }
+    }
+
+    // Void blocks can still end in a call to another void function, which is
+    // then checked like a statement.
+    fn check_void_block(&mut self, block: &Block) {
+        for stmt in &block.statements {
+            self.check_statement(stmt);
+        }
+
+        if let Some(result) = &block.result {
+            self.check_expression_statement(result);
+        }
+    }
+
+    fn check_expression(&mut self, expr: &Expr, expected: Type) {
+        match &expr.kind {
+            ExprKind::Number(number) => {
+                if Value::from_number(*number, expected).is_none() {
+                    self.errors.push(CompileError::NumberOutOfRange {
+                        value: number.to_string(),
+                        r#type: expected,
+                        span: expr.span,
+                    });
+                }
+            }
+            ExprKind::Float(_) => {
+                if !expected.is_float() {
+                    self.unify(expected, Some(Type::F64), expr.span);
+                }
+            }
+            ExprKind::String(_) => self.unify(expected, Some(Type::Array(Element::U8)), expr.span),
+            ExprKind::Variable(_) => {
+                let found = types::infer(expr, self);
+                self.unify(expected, found, expr.span);
+            }
+            ExprKind::Assign { target, value } => {
+                if let Some(Variable::Constant(_)) = self.symbols.variable(self.name, target) {
+                    self.errors.push(CompileError::AssignToConstant {
+                        name: target.name.clone(),
+                        span: target.span,
+                    });
+                }
+
+                match self.variable(target) {
+                    Some(r#type) => {
+                        self.check_expression(value, r#type);
+                        self.unify(expected, Some(r#type), expr.span);
+                    }
+                    None => self.check_expression(value, expected),
+                }
+            }
+            ExprKind::Index { array, index } => {
+                self.check_array(array);
+                self.check_expression(index, Type::I32);
+                self.unify(expected, Some(Type::I32), expr.span);
+            }
+            ExprKind::IndexAssign {
+                array,
+                index,
+                value,
+            } => {
+                self.check_array(array);
+                self.check_expression(index, Type::I32);
+                self.check_expression(value, Type::I32);
+                self.unify(expected, Some(Type::I32), expr.span);
+            }
+            ExprKind::Field { object, field } => {
+                let found = self.check_field(object, field);
+                self.unify(expected, found, expr.span);
+            }
+            ExprKind::FieldAssign {
+                object,
+                field,
+                value,
+            } => {
+                if let Some(r#type) = self.check_field(object, field) {
+                    self.check_expression(value, r#type);
+                    self.unify(expected, Some(r#type), expr.span);
+                }
+            }
+            ExprKind::Struct { name, fields } => {
+                let found = self.check_struct(name, fields);
+                self.unify(expected, found, expr.span);
+            }
+            ExprKind::Array(elements) => {
+                for element in elements {
+                    self.check_expression(element, Type::I32);
+                }
+
+                self.unify(expected, Some(Type::Array(Element::I32)), expr.span);
+            }
+            ExprKind::Variant { r#enum, variant } => {
+                let found = self.check_variant(r#enum, variant);
+                self.unify(expected, found, expr.span);
+            }
+            ExprKind::Match { value, arms } => {
+                let r#type = types::infer(expr, self).unwrap_or(expected);
+
+                self.check_match(value, arms, Some(r#type));
+                self.unify(expected, Some(r#type), expr.span);
+            }
+            ExprKind::Unary { operator, operand } => {
+                let r#type = types::unary_operand(*operator, operand, expected, self);
+
+                self.check_expression(operand, r#type);
+
+                if r#type.is_float() && *operator != UnaryOperator::Negate {
+                    self.unify(Type::I32, Some(r#type), expr.span);
+                } else if *operator == UnaryOperator::Not {
+                    self.unify(expected, Some(Type::I32), expr.span);
+                } else {
+                    self.unify(expected, Some(r#type), expr.span);
+                }
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } if types::is_logical(*operator) => {
+                self.check_expression(left, Type::I32);
+                self.check_expression(right, Type::I32);
+                self.unify(expected, Some(Type::I32), expr.span);
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                let comparison = types::is_comparison(*operator);
+                let operands = types::infer(left, self)
+                    .or_else(|| types::infer(right, self))
+                    .unwrap_or_else(|| {
+                        if comparison {
+                            types::default_operands(left, right)
+                        } else {
+                            expected
+                        }
+                    });
+
+                self.check_expression(left, operands);
+                self.check_expression(right, operands);
+
+                if comparison {
+                    self.unify(expected, Some(Type::I32), expr.span);
+                } else if operands.is_float() && types::is_integer_only(*operator) {
+                    self.unify(Type::I32, Some(operands), expr.span);
+                } else {
+                    self.unify(expected, Some(operands), expr.span);
+                }
+            }
+            ExprKind::Call {
+                function,
+                arguments,
+            } => match function.name.as_str() {
+                "__trap" => (),
+                "__memorySize" | "__memoryGrow" => {
+                    for argument in arguments {
+                        self.check_expression(argument, Type::I32);
+                    }
+
+                    self.unify(expected, Some(Type::I32), expr.span);
+                }
+                "concat" => {
+                    for argument in arguments {
+                        self.check_expression(argument, Type::Array(Element::U8));
+                    }
+
+                    self.unify(expected, Some(Type::Array(Element::U8)), expr.span);
+                }
+                "len" => {
+                    for argument in arguments {
+                        self.check_array(argument);
+                    }
+
+                    self.unify(expected, Some(Type::I32), expr.span);
+                }
+                "i32" | "u32" | "i64" | "u64" | "f32" | "f64" => {
+                    let target =
+                        Type::from_name(&function.name).expect("conversions are named after types");
+
+                    if let [argument] = &arguments[..] {
+                        let source = types::conversion_source(argument, target, self);
+                        self.check_expression(argument, source);
+                    }
+
+                    self.unify(expected, Some(target), expr.span);
+                }
+                name => {
+                    // Unknown functions are reported by code generation.
+                    if self.symbols.function(name).is_none() {
+                        return;
+                    }
+
+                    self.check_arguments(name, arguments);
+
+                    if self.symbols.is_void(name) {
+                        self.errors.push(CompileError::MissingValue {
+                            expected: self.describe(expected),
+                            span: expr.span,
+                        });
+                    } else {
+                        let found = self.symbols.result(name);
+                        self.unify(expected, found, expr.span);
+                    }
+                }
+            },
+            ExprKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let r#type = types::infer(expr, self).unwrap_or(expected);
+
+                self.check_expression(condition, Type::I32);
+                self.check_block(then_block, r#type);
+                self.check_block(else_block, r#type);
+                self.unify(expected, Some(r#type), expr.span);
+            }
+        }
+    }
+}
+
+// Whether control can never reach the end of the block, because every path
+// through it returns or traps.
+fn diverges(block: &Block) -> bool {
+    block.statements.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::If {
+            then_block,
+            else_block: Some(else_block),
+            ..
+        } => diverges(then_block) && diverges(else_block),
+        StmtKind::Expr(Expr {
+            kind: ExprKind::Call { function, .. },
+            ..
+        }) => function.name == "__trap",
+        _ => false,
+    })
+}
+
+pub fn check_function(function: &Function, symbols: &Symbols, errors: &mut Vec<CompileError>) {
+    let name = &function.name.name;
+    let result = symbols.result(name);
+
+    if let Some(r#type) = result
+        && function.body.result.is_none()
+        && !diverges(&function.body)
+    {
+        errors.push(CompileError::MissingReturn {
+            name: name.clone(),
+            r#type: symbols.type_names().describe(r#type),
+            span: function.name.span,
+        });
+    }
+
+    let mut checker = Checker {
+        name,
+        result,
+        symbols,
+        errors,
+    };
+
+    checker.check_body(&function.body);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wafer::symbols::Symbols;
+    use crate::wafer::{CompileError, Source, Span, parse};
+
+    use super::check_function;
+
+    fn check(input: &str) -> Vec<CompileError> {
+        let module = parse(input, Source::Input).unwrap();
+        let mut errors = vec![];
+        let symbols = Symbols::new(&module, &mut errors);
+
+        for function in &module.functions {
+            check_function(function, &symbols, &mut errors);
+        }
+
+        errors
+    }
+
+    fn mismatch(expected: &str, found: &str, start: usize, end: usize) -> CompileError {
+        CompileError::MismatchedTypes {
+            expected: expected.to_string(),
+            found: found.to_string(),
+            span: Span::new(start, end),
+        }
+    }
+
+    #[test]
+    fn should_accept_well_typed_programs() {
+        let input = r"
+            extern func sin(x: f64): f64;
+
+            func wave(t: f64, scale: i64): i64 {
+                let y = sin(t) * 2.0;
+                if y > 0.5 { i64(y) * scale } else { 0 }
+            }
+        ";
+
+        assert_eq!(check(input), vec![]);
+    }
+
+    #[test]
+    fn should_accept_void_calls_ending_void_bodies() {
+        let input = "extern func print(a); func main() { print(1) } func f(): i32 { main(); 0 }";
+
+        assert_eq!(check(input), vec![]);
+    }
+
+    #[test]
+    fn should_reject_mismatched_operands() {
+        let input = "func f(a: i64, b: i32): i64 { a + b }";
+
+        assert_eq!(check(input), vec![mismatch("i64", "i32", 34, 35)]);
+    }
+
+    #[test]
+    fn should_reject_mismatched_arguments() {
+        let input = "func g(x: f32) { 0 } func f(a: i64) { g(a) }";
+
+        assert_eq!(check(input), vec![mismatch("f32", "i64", 40, 41)]);
+    }
+
+    #[test]
+    fn should_reject_disagreeing_if_branches() {
+        let input = "func f(a: i64, b): i64 { if b { a } else { b } }";
+
+        assert_eq!(check(input), vec![mismatch("i64", "i32", 43, 44)]);
+    }
+
+    #[test]
+    fn should_reject_mismatched_assignments() {
+        let input = "func f(a: f64): i32 { let b: i64 = 1; b := a; 0 }";
+
+        assert_eq!(check(input), vec![mismatch("i64", "f64", 43, 44)]);
+    }
+
+    #[test]
+    fn should_reject_wrong_result_type() {
+        let input = "func f(a: f64): i32 { a }";
+
+        assert_eq!(check(input), vec![mismatch("i32", "f64", 22, 23)]);
+    }
+
+    #[test]
+    fn should_reject_non_i32_conditions() {
+        let input = "func f(a: i64) { while a { a := a - 1; } 0 }";
+
+        assert_eq!(check(input), vec![mismatch("i32", "i64", 23, 24)]);
+    }
+
+    #[test]
+    fn should_check_return_statements() {
+        let input = r"
+            func log(a) { if a { return; } log(a - 1); }
+            func abs(a): i32 { if a < 0 { return 0 - a; } else { return a; } }
+        ";
+
+        assert_eq!(check(input), vec![]);
+    }
+
+    #[test]
+    fn should_reject_returns_that_disagree_with_the_signature() {
+        let input = "func a() { return 1; } func b(): i64 { return; }";
+
+        assert_eq!(
+            check(input),
+            vec![
+                CompileError::UnexpectedValue {
+                    span: Span::new(18, 19),
+                },
+                CompileError::MissingValue {
+                    expected: "i64".to_string(),
+                    span: Span::new(39, 46),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_missing_returns() {
+        let input = "func f(a): i32 { if a { return 1; } }";
+
+        assert_eq!(
+            check(input),
+            vec![CompileError::MissingReturn {
+                name: "f".to_string(),
+                r#type: "i32".to_string(),
+                span: Span::new(5, 6),
+            }]
+        );
+    }
+
+    #[test]
+    fn should_reject_void_calls_used_as_values() {
+        let input = "func log(a) { } func f() { log(1) + 1 }";
+
+        assert_eq!(
+            check(input),
+            vec![CompileError::MissingValue {
+                expected: "i32".to_string(),
+                span: Span::new(27, 33),
+            }]
+        );
+    }
+
+    #[test]
+    fn should_reject_assignments_to_constants() {
+        let input = "const A = 1; func f() { A := 2 }";
+
+        assert_eq!(
+            check(input),
+            vec![CompileError::AssignToConstant {
+                name: "A".to_string(),
+                span: Span::new(24, 25),
+            }]
+        );
+    }
+
+    #[test]
+    fn should_reject_mismatched_structs() {
+        let input =
+            "struct P { x } struct Q { a: f64 } func f(p: P) { p.x } func g() { f(Q { a: 1.5 }) }";
+
+        assert_eq!(check(input), vec![mismatch("P", "Q", 69, 81)]);
+    }
+
+    #[test]
+    fn should_reject_arrays_of_other_elements() {
+        let input = "func g(a: Int32Array) { a[0] } func f() { let b: Int32Array = \"abcd\"; \
+            let c: Uint16Array = [1]; let d: Uint8Array = 0; g(\"xy\") + b[0] + c[0] + d[0] }";
+
+        assert_eq!(
+            check(input),
+            vec![
+                mismatch("Int32Array", "Uint8Array", 63, 67),
+                mismatch("Uint16Array", "Int32Array", 91, 94),
+                mismatch("Int32Array", "Uint8Array", 122, 124),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_check_struct_fields() {
+        let input = "struct P { x, y: f64 } func f(p: P) { let q = P { x: 1, z: 2 }; p.w + q.x }";
+        assert_eq!(
+            check(input),
+            vec![
+                CompileError::UnknownField {
+                    name: "z".to_string(),
+                    r#type: "P".to_string(),
+                    span: Span::new(56, 57),
+                },
+                CompileError::MissingField {
+                    name: "y".to_string(),
+                    r#type: "P".to_string(),
+                    span: Span::new(46, 47),
+                },
+                CompileError::UnknownField {
+                    name: "w".to_string(),
+                    r#type: "P".to_string(),
+                    span: Span::new(66, 67),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_mismatched_fields() {
+        let input = "struct P { x: f64 } func f(p: P): i64 { p.x := p; P { x: 1 } }";
+        assert_eq!(
+            check(input),
+            vec![mismatch("f64", "P", 47, 48), mismatch("i64", "P", 50, 60),]
+        );
+    }
+
+    #[test]
+    fn should_require_exhaustive_matches() {
+        let input =
+            "enum E { A, B, C } func f(e: E, n) { match e { E::B => 1 } + match n { 0 => 1 } }";
+
+        assert_eq!(
+            check(input),
+            vec![
+                CompileError::NonExhaustiveMatch {
+                    missing: vec!["E::A".to_string(), "E::C".to_string()],
+                    span: Span::new(43, 44),
+                },
+                CompileError::NonExhaustiveMatch {
+                    missing: vec!["_".to_string()],
+                    span: Span::new(67, 68),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_unreachable_patterns() {
+        let input = "func f(n) { match n { -1 => 1, 2 => 2, -1 => 3, _ => 4, _ => 5, 6 => 6 } }";
+
+        assert_eq!(
+            check(input),
+            vec![
+                CompileError::UnreachablePattern {
+                    span: Span::new(39, 41),
+                },
+                CompileError::UnreachablePattern {
+                    span: Span::new(56, 57),
+                },
+                CompileError::UnreachablePattern {
+                    span: Span::new(64, 65),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_variants_of_other_enums() {
+        let input =
+            "enum E { A } enum F { A } func f(e: E) { match e { F::A => 1, E::B => 2, _ => 3 } }";
+
+        assert_eq!(
+            check(input),
+            vec![
+                mismatch("E", "F", 51, 55),
+                CompileError::UnknownVariant {
+                    name: "B".to_string(),
+                    r#type: "E".to_string(),
+                    span: Span::new(65, 66),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_integer_operators_on_floats() {
+        let input = "func f(a: f64) { let b = a & a; let c = a % a; 0 }";
+
+        assert_eq!(
+            check(input),
+            vec![
+                mismatch("i32", "f64", 25, 30),
+                mismatch("i32", "f64", 40, 45),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_require_conversions_between_signed_and_unsigned() {
+        let input = "func f(a: u32, b: i64): u32 { let c: i32 = a; u32(c) + u32(u64(b)) }";
+
+        assert_eq!(check(input), vec![mismatch("i32", "u32", 43, 44)]);
+    }
+
+    #[test]
+    fn should_check_logical_operands_as_conditions() {
+        let input = "func f(a: f64): i32 { a > 0 && a }";
+
+        assert_eq!(check(input), vec![mismatch("i32", "f64", 31, 32)]);
+    }
+}
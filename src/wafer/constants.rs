@@ -0,0 +1,491 @@
+use crate::wasm::Instruction;
+
+use super::ast::{BinaryOperator, Expr, ExprKind, Identifier, UnaryOperator};
+use super::types::{self, Element, Environment, Type};
+use super::{CompileError, Span};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Value {
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+}
+
+impl Value {
+    pub fn zero(r#type: Type) -> Self {
+        match r#type {
+            Type::I32 | Type::Array(_) | Type::Struct(_) | Type::Enum(_) => Value::I32(0),
+            Type::U32 => Value::U32(0),
+            Type::I64 => Value::I64(0),
+            Type::U64 => Value::U64(0),
+            Type::F32 => Value::F32(0.0),
+            Type::F64 => Value::F64(0.0),
+        }
+    }
+
+    // Converts a literal to the given type, if it is in range. Floats take any
+    // literal, rounding it to the nearest representable value.
+    pub fn from_number(number: i128, r#type: Type) -> Option<Self> {
+        Some(match r#type {
+            Type::I32 | Type::Array(_) | Type::Struct(_) | Type::Enum(_) => {
+                Value::I32(i32::try_from(number).ok()?)
+            }
+            Type::U32 => Value::U32(u32::try_from(number).ok()?),
+            Type::I64 => Value::I64(i64::try_from(number).ok()?),
+            Type::U64 => Value::U64(u64::try_from(number).ok()?),
+            Type::F32 => Value::F32(number as f32),
+            Type::F64 => Value::F64(number as f64),
+        })
+    }
+
+    pub fn integer(self) -> Option<i128> {
+        match self {
+            Value::I32(value) => Some(value.into()),
+            Value::U32(value) => Some(value.into()),
+            Value::I64(value) => Some(value.into()),
+            Value::U64(value) => Some(value.into()),
+            Value::F32(_) | Value::F64(_) => None,
+        }
+    }
+
+    pub fn r#type(self) -> Type {
+        match self {
+            Value::I32(_) => Type::I32,
+            Value::U32(_) => Type::U32,
+            Value::I64(_) => Type::I64,
+            Value::U64(_) => Type::U64,
+            Value::F32(_) => Type::F32,
+            Value::F64(_) => Type::F64,
+        }
+    }
+
+    pub fn instruction(self) -> Instruction {
+        match self {
+            Value::I32(value) => Instruction::ConstI32(value),
+            Value::U32(value) => Instruction::ConstI32(value as i32),
+            Value::I64(value) => Instruction::ConstI64(value),
+            Value::U64(value) => Instruction::ConstI64(value as i64),
+            Value::F32(value) => Instruction::ConstF32(value),
+            Value::F64(value) => Instruction::ConstF64(value),
+        }
+    }
+}
+
+pub trait Constants: Environment {
+    fn constant(&self, identifier: &Identifier) -> Option<Value>;
+    // String literals are constants too: their offset in the data segment.
+    fn string(&self, value: &str) -> Option<i32>;
+}
+
+fn expect(
+    expected: Type,
+    value: Value,
+    span: Span,
+    env: &impl Constants,
+) -> Result<Value, CompileError> {
+    if expected.accepts(value.r#type()) {
+        Ok(value)
+    } else {
+        Err(CompileError::mismatched_types(
+            expected,
+            value.r#type(),
+            span,
+            env.type_names(),
+        ))
+    }
+}
+
+// Evaluates an initializer at compile time, applying the same typing rules as
+// the checker. Only literals, constants and operators are allowed, since
+// WebAssembly needs global initializers to be constant.
+pub fn evaluate(expr: &Expr, expected: Type, env: &impl Constants) -> Result<Value, CompileError> {
+    match &expr.kind {
+        ExprKind::Number(number) => {
+            Value::from_number(*number, expected).ok_or(CompileError::NumberOutOfRange {
+                value: number.to_string(),
+                r#type: expected,
+                span: expr.span,
+            })
+        }
+        ExprKind::Float(number) => match expected {
+            Type::F32 => Ok(Value::F32(*number as f32)),
+            Type::F64 => Ok(Value::F64(*number)),
+            _ => expect(expected, Value::F64(*number), expr.span, env),
+        },
+        ExprKind::String(value) => {
+            let offset = env
+                .string(value)
+                .ok_or(CompileError::NotConstant { span: expr.span })?;
+            let found = Type::Array(Element::U8);
+
+            if expected.accepts(found) {
+                Ok(Value::I32(offset))
+            } else {
+                Err(CompileError::mismatched_types(
+                    expected,
+                    found,
+                    expr.span,
+                    env.type_names(),
+                ))
+            }
+        }
+        ExprKind::Variable(identifier) => match env.constant(identifier) {
+            Some(value) => expect(expected, value, expr.span, env),
+            None if env.variable(identifier).is_none() => Err(CompileError::UnknownVariable {
+                name: identifier.name.clone(),
+                span: identifier.span,
+            }),
+            None => Err(CompileError::NotConstant { span: expr.span }),
+        },
+        ExprKind::Binary {
+            operator,
+            left,
+            right,
+        } if types::is_logical(*operator) => {
+            let left = evaluate(left, Type::I32, env)?;
+            let right = evaluate(right, Type::I32, env)?;
+            let value = apply(*operator, left, right)
+                .ok_or(CompileError::NotConstant { span: expr.span })?;
+
+            expect(expected, value, expr.span, env)
+        }
+        ExprKind::Binary {
+            operator,
+            left,
+            right,
+        } => {
+            let comparison = types::is_comparison(*operator);
+            let operands = types::infer(left, env)
+                .or_else(|| types::infer(right, env))
+                .unwrap_or_else(|| {
+                    if comparison {
+                        types::default_operands(left, right)
+                    } else {
+                        expected
+                    }
+                });
+
+            let left = evaluate(left, operands, env)?;
+            let right = evaluate(right, operands, env)?;
+
+            if operands.is_float() && types::is_integer_only(*operator) {
+                expect(Type::I32, left, expr.span, env)?;
+            }
+
+            let value =
+                apply(*operator, left, right).ok_or_else(|| match (operator, right.integer()) {
+                    (BinaryOperator::Divide | BinaryOperator::Remainder, Some(0)) => {
+                        CompileError::DivisionByZero { span: expr.span }
+                    }
+                    _ => CompileError::NotConstant { span: expr.span },
+                })?;
+
+            expect(expected, value, expr.span, env)
+        }
+        ExprKind::Unary { operator, operand } => {
+            let r#type = types::unary_operand(*operator, operand, expected, env);
+            let value = evaluate(operand, r#type, env)?;
+
+            if r#type.is_float() && *operator != UnaryOperator::Negate {
+                expect(Type::I32, value, expr.span, env)?;
+            }
+
+            let value = apply_unary(*operator, value)
+                .ok_or(CompileError::NotConstant { span: expr.span })?;
+
+            expect(expected, value, expr.span, env)
+        }
+        _ => Err(CompileError::NotConstant { span: expr.span }),
+    }
+}
+
+fn compare<T: PartialOrd>(operator: BinaryOperator, left: T, right: T) -> bool {
+    match operator {
+        BinaryOperator::Equal => left == right,
+        BinaryOperator::NotEqual => left != right,
+        BinaryOperator::LessThan => left < right,
+        BinaryOperator::GreaterThan => left > right,
+        BinaryOperator::LessThanOrEqual => left <= right,
+        BinaryOperator::GreaterThanOrEqual => left >= right,
+        _ => unreachable!("only called for comparisons"),
+    }
+}
+
+// Integer arithmetic wraps like the WebAssembly instructions it replaces, and
+// shift counts are taken modulo the bit width; division by zero and
+// overflowing division, which would trap, give `None`.
+pub fn apply(operator: BinaryOperator, left: Value, right: Value) -> Option<Value> {
+    if types::is_comparison(operator) {
+        let result = match (left, right) {
+            (Value::I32(left), Value::I32(right)) => compare(operator, left, right),
+            (Value::U32(left), Value::U32(right)) => compare(operator, left, right),
+            (Value::I64(left), Value::I64(right)) => compare(operator, left, right),
+            (Value::U64(left), Value::U64(right)) => compare(operator, left, right),
+            (Value::F32(left), Value::F32(right)) => compare(operator, left, right),
+            (Value::F64(left), Value::F64(right)) => compare(operator, left, right),
+            _ => return None,
+        };
+
+        return Some(Value::I32(result.into()));
+    }
+
+    match (left, right) {
+        (Value::I32(left), Value::I32(right)) => Some(Value::I32(match operator {
+            BinaryOperator::Add => left.wrapping_add(right),
+            BinaryOperator::Subtract => left.wrapping_sub(right),
+            BinaryOperator::Multiply => left.wrapping_mul(right),
+            BinaryOperator::Divide => left.checked_div(right)?,
+            BinaryOperator::Remainder if right == 0 => return None,
+            BinaryOperator::Remainder => left.wrapping_rem(right),
+            BinaryOperator::And => left & right,
+            BinaryOperator::Or => left | right,
+            BinaryOperator::Xor => left ^ right,
+            BinaryOperator::ShiftLeft => left.wrapping_shl(right as u32),
+            BinaryOperator::ShiftRight => left.wrapping_shr(right as u32),
+            BinaryOperator::ShiftRightUnsigned => (left as u32).wrapping_shr(right as u32) as i32,
+            BinaryOperator::LogicalAnd => (left != 0 && right != 0).into(),
+            BinaryOperator::LogicalOr => (left != 0 || right != 0).into(),
+            _ => return None,
+        })),
+        (Value::I64(left), Value::I64(right)) => Some(Value::I64(match operator {
+            BinaryOperator::Add => left.wrapping_add(right),
+            BinaryOperator::Subtract => left.wrapping_sub(right),
+            BinaryOperator::Multiply => left.wrapping_mul(right),
+            BinaryOperator::Divide => left.checked_div(right)?,
+            BinaryOperator::Remainder if right == 0 => return None,
+            BinaryOperator::Remainder => left.wrapping_rem(right),
+            BinaryOperator::And => left & right,
+            BinaryOperator::Or => left | right,
+            BinaryOperator::Xor => left ^ right,
+            BinaryOperator::ShiftLeft => left.wrapping_shl(right as u32),
+            BinaryOperator::ShiftRight => left.wrapping_shr(right as u32),
+            BinaryOperator::ShiftRightUnsigned => (left as u64).wrapping_shr(right as u32) as i64,
+            _ => return None,
+        })),
+        (Value::U32(left), Value::U32(right)) => Some(Value::U32(match operator {
+            BinaryOperator::Add => left.wrapping_add(right),
+            BinaryOperator::Subtract => left.wrapping_sub(right),
+            BinaryOperator::Multiply => left.wrapping_mul(right),
+            BinaryOperator::Divide => left.checked_div(right)?,
+            BinaryOperator::Remainder => left.checked_rem(right)?,
+            BinaryOperator::And => left & right,
+            BinaryOperator::Or => left | right,
+            BinaryOperator::Xor => left ^ right,
+            BinaryOperator::ShiftLeft => left.wrapping_shl(right),
+            BinaryOperator::ShiftRight | BinaryOperator::ShiftRightUnsigned => {
+                left.wrapping_shr(right)
+            }
+            _ => return None,
+        })),
+        (Value::U64(left), Value::U64(right)) => Some(Value::U64(match operator {
+            BinaryOperator::Add => left.wrapping_add(right),
+            BinaryOperator::Subtract => left.wrapping_sub(right),
+            BinaryOperator::Multiply => left.wrapping_mul(right),
+            BinaryOperator::Divide => left.checked_div(right)?,
+            BinaryOperator::Remainder => left.checked_rem(right)?,
+            BinaryOperator::And => left & right,
+            BinaryOperator::Or => left | right,
+            BinaryOperator::Xor => left ^ right,
+            BinaryOperator::ShiftLeft => left.wrapping_shl(right as u32),
+            BinaryOperator::ShiftRight | BinaryOperator::ShiftRightUnsigned => {
+                left.wrapping_shr(right as u32)
+            }
+            _ => return None,
+        })),
+        (Value::F32(left), Value::F32(right)) => Some(Value::F32(match operator {
+            BinaryOperator::Add => left + right,
+            BinaryOperator::Subtract => left - right,
+            BinaryOperator::Multiply => left * right,
+            BinaryOperator::Divide => left / right,
+            _ => return None,
+        })),
+        (Value::F64(left), Value::F64(right)) => Some(Value::F64(match operator {
+            BinaryOperator::Add => left + right,
+            BinaryOperator::Subtract => left - right,
+            BinaryOperator::Multiply => left * right,
+            BinaryOperator::Divide => left / right,
+            _ => return None,
+        })),
+        _ => None,
+    }
+}
+
+pub fn apply_unary(operator: UnaryOperator, value: Value) -> Option<Value> {
+    Some(match (operator, value) {
+        (UnaryOperator::Negate, Value::I32(value)) => Value::I32(value.wrapping_neg()),
+        (UnaryOperator::Negate, Value::U32(value)) => Value::U32(value.wrapping_neg()),
+        (UnaryOperator::Negate, Value::I64(value)) => Value::I64(value.wrapping_neg()),
+        (UnaryOperator::Negate, Value::U64(value)) => Value::U64(value.wrapping_neg()),
+        (UnaryOperator::Negate, Value::F32(value)) => Value::F32(-value),
+        (UnaryOperator::Negate, Value::F64(value)) => Value::F64(-value),
+        (UnaryOperator::Not, Value::I32(value)) => Value::I32((value == 0).into()),
+        (UnaryOperator::Not, Value::U32(value)) => Value::I32((value == 0).into()),
+        (UnaryOperator::Not, Value::I64(value)) => Value::I32((value == 0).into()),
+        (UnaryOperator::Not, Value::U64(value)) => Value::I32((value == 0).into()),
+        (UnaryOperator::Complement, Value::I32(value)) => Value::I32(!value),
+        (UnaryOperator::Complement, Value::U32(value)) => Value::U32(!value),
+        (UnaryOperator::Complement, Value::I64(value)) => Value::I64(!value),
+        (UnaryOperator::Complement, Value::U64(value)) => Value::U64(!value),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::wafer::ast::Identifier;
+    use crate::wafer::types::{Element, Environment, Type, TypeNames};
+    use crate::wafer::{CompileError, Source, Span, parse};
+
+    use super::{Constants, Value, evaluate};
+
+    const NAMES: &TypeNames = &TypeNames::new();
+
+    struct Env(HashMap<&'static str, Value>);
+
+    impl Environment for Env {
+        fn variable(&self, identifier: &Identifier) -> Option<Type> {
+            self.0
+                .get(identifier.name.as_str())
+                .map(|value| value.r#type())
+        }
+
+        fn result(&self, _: &str) -> Option<Type> {
+            None
+        }
+
+        fn field(&self, _: Type, _: &str) -> Option<Type> {
+            None
+        }
+
+        fn type_names(&self) -> &TypeNames {
+            NAMES
+        }
+    }
+
+    impl Constants for Env {
+        fn constant(&self, identifier: &Identifier) -> Option<Value> {
+            self.0.get(identifier.name.as_str()).copied()
+        }
+
+        fn string(&self, value: &str) -> Option<i32> {
+            (value == "hi").then_some(16)
+        }
+    }
+
+    fn evaluate_as(input: &str, r#type: Type) -> Result<Value, CompileError> {
+        let env = Env(HashMap::from([
+            ("WIDTH", Value::I32(256)),
+            ("BIG", Value::I64(1 << 40)),
+        ]));
+        let module = parse(&format!("func f() {{ {input} }}"), Source::Input).unwrap();
+        let expr = module.functions[0].body.result.as_deref().unwrap();
+
+        evaluate(expr, r#type, &env)
+    }
+
+    #[test]
+    fn should_fold_constant_expressions() {
+        assert_eq!(evaluate_as("WIDTH * 2 + 1", Type::I32), Ok(Value::I32(513)));
+        assert_eq!(
+            evaluate_as("BIG / 1024", Type::I64),
+            Ok(Value::I64(1 << 30))
+        );
+        assert_eq!(evaluate_as("1.5 * 2", Type::F32), Ok(Value::F32(3.0)));
+        assert_eq!(evaluate_as("WIDTH > 100", Type::I32), Ok(Value::I32(1)));
+    }
+
+    #[test]
+    fn should_fold_bitwise_and_unary_operators() {
+        assert_eq!(evaluate_as("-WIDTH % 100", Type::I32), Ok(Value::I32(-56)));
+        assert_eq!(evaluate_as("~WIDTH ^ 1", Type::I32), Ok(Value::I32(-258)));
+        assert_eq!(evaluate_as("-1 >>> 28", Type::I32), Ok(Value::I32(15)));
+        assert_eq!(evaluate_as("1 << 33", Type::I32), Ok(Value::I32(2)));
+        assert_eq!(evaluate_as("BIG >> 40", Type::I64), Ok(Value::I64(1)));
+        assert_eq!(evaluate_as("!BIG", Type::I32), Ok(Value::I32(0)));
+        assert_eq!(evaluate_as("-(1.5)", Type::F32), Ok(Value::F32(-1.5)));
+    }
+
+    #[test]
+    fn should_fold_unsigned_operators() {
+        assert_eq!(evaluate_as("0xFFFFFFF0 % 7", Type::U32), Ok(Value::U32(2)));
+        assert_eq!(
+            evaluate_as("0x80000000 >> 31", Type::U32),
+            Ok(Value::U32(1))
+        );
+        assert_eq!(evaluate_as("0 - 1", Type::U64), Ok(Value::U64(u64::MAX)));
+        assert_eq!(
+            evaluate_as("-1", Type::U32),
+            Err(CompileError::NumberOutOfRange {
+                value: "-1".to_string(),
+                r#type: Type::U32,
+                span: Span::new(11, 13),
+            })
+        );
+    }
+
+    #[test]
+    fn should_evaluate_strings_to_their_offset() {
+        assert_eq!(
+            evaluate_as(r#""hi""#, Type::Array(Element::U8)),
+            Ok(Value::I32(16))
+        );
+        assert_eq!(
+            evaluate_as(r#""hi""#, Type::I64),
+            Err(CompileError::MismatchedTypes {
+                expected: "i64".to_string(),
+                found: "Uint8Array".to_string(),
+                span: Span::new(12, 14),
+            })
+        );
+    }
+
+    #[test]
+    fn should_wrap_integer_overflow() {
+        assert_eq!(
+            evaluate_as("2147483647 + 1", Type::I32),
+            Ok(Value::I32(i32::MIN))
+        );
+    }
+
+    #[test]
+    fn should_reject_non_constant_expressions() {
+        assert_eq!(
+            evaluate_as("WIDTH / 0", Type::I32),
+            Err(CompileError::DivisionByZero {
+                span: Span::new(11, 20),
+            })
+        );
+        assert_eq!(
+            evaluate_as("-2147483648 / -1", Type::I32),
+            Err(CompileError::NotConstant {
+                span: Span::new(11, 27),
+            })
+        );
+        assert_eq!(
+            evaluate_as("BIG + 1", Type::I32),
+            Err(CompileError::MismatchedTypes {
+                expected: "i32".to_string(),
+                found: "i64".to_string(),
+                span: Span::new(11, 18),
+            })
+        );
+        assert_eq!(
+            evaluate_as("f()", Type::I32),
+            Err(CompileError::NotConstant {
+                span: Span::new(11, 14),
+            })
+        );
+        assert_eq!(
+            evaluate_as("height", Type::I32),
+            Err(CompileError::UnknownVariable {
+                name: "height".to_string(),
+                span: Span::new(11, 17),
+            })
+        );
+    }
+}
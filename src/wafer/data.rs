@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use super::Span;
+use super::ast::{self, Expr, ExprKind, Module, Visitor};
+
+// The data segment holds string literals and constant array literals, laid out
+// like the arrays the prelude allocates: their length followed by the
+// elements. Strings are the UTF-8 bytes of a `Uint8Array`, padded so that
+// every header is aligned.
+#[derive(Default)]
+pub struct Data {
+    strings: HashMap<String, usize>,
+    arrays: HashMap<Span, usize>,
+    bytes: Vec<u8>,
+}
+
+// Array literals made only of numbers, strings and other constant arrays are
+// laid out at compile time. Like a string literal, every evaluation of such a
+// literal returns the same array, so writes to it are seen by the evaluations
+// that follow: `func f() { let a = [0]; a[0] := a[0] + 1; a[0] }` counts its
+// calls. Arrays that need to start out fresh are built with `newInt32Array`.
+pub fn is_constant(elements: &[Expr]) -> bool {
+    elements.iter().all(|element| match &element.kind {
+        ExprKind::Number(number) => i32::try_from(*number).is_ok(),
+        ExprKind::String(_) => true,
+        ExprKind::Array(elements) => is_constant(elements),
+        _ => false,
+    })
+}
+
+impl Visitor for Data {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::String(value) => {
+                self.add_string(value);
+            }
+            ExprKind::Array(elements) if is_constant(elements) => {
+                self.add_array(elements, expr.span);
+                return;
+            }
+            _ => (),
+        }
+
+        ast::walk_expr(self, expr);
+    }
+}
+
+impl From<&Module> for Data {
+    fn from(module: &Module) -> Self {
+        let mut data = Data::default();
+
+        for global in &module.globals {
+            data.visit_expr(&global.value);
+        }
+
+        for function in &module.functions {
+            ast::walk_block(&mut data, &function.body);
+        }
+
+        data
+    }
+}
+
+impl Data {
+    fn add_string(&mut self, value: &str) -> usize {
+        if let Some(offset) = self.strings.get(value) {
+            return *offset;
+        }
+
+        let offset = self.bytes.len();
+        self.strings.insert(value.to_owned(), offset);
+
+        self.bytes.extend((value.len() as i32).to_le_bytes());
+        self.bytes.extend(value.as_bytes());
+        self.bytes.resize(self.bytes.len().next_multiple_of(4), 0);
+
+        offset
+    }
+
+    // Nested arrays and strings are laid out first, so that their addresses
+    // can be stored as elements.
+    fn add_array(&mut self, elements: &[Expr], span: Span) -> usize {
+        let values: Vec<_> = elements
+            .iter()
+            .map(|element| match &element.kind {
+                ExprKind::Number(number) => *number as i32,
+                ExprKind::String(value) => self.add_string(value) as i32,
+                ExprKind::Array(elements) => self.add_array(elements, element.span) as i32,
+                _ => unreachable!("only constant arrays are laid out"),
+            })
+            .collect();
+
+        let offset = self.bytes.len();
+        self.arrays.insert(span, offset);
+
+        self.bytes.extend((values.len() as i32).to_le_bytes());
+
+        for value in values {
+            self.bytes.extend(value.to_le_bytes());
+        }
+
+        offset
+    }
+
+    pub fn string(&self, string: &str) -> Option<i32> {
+        self.strings.get(string).map(|offset| *offset as i32)
+    }
+
+    pub fn array(&self, span: Span) -> Option<i32> {
+        self.arrays.get(&span).map(|offset| *offset as i32)
+    }
+
+    pub fn len(&self) -> i32 {
+        self.bytes.len() as i32
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wafer::{Source, Span, parse};
+
+    use super::Data;
+
+    #[test]
+    fn should_collect_strings() {
+        let module = parse(
+            r#"func main() { let a = "foo"; let b = "bar"; 0 }"#,
+            Source::Input,
+        )
+        .unwrap();
+
+        let data = Data::from(&module);
+
+        assert_eq!(data.string("foo"), Some(0));
+        assert_eq!(data.string("bar"), Some(8));
+        assert_eq!(data.string("baz"), None);
+        assert_eq!(data.len(), 16);
+
+        assert_eq!(
+            data.into_bytes(),
+            vec![
+                3, 0, 0, 0, 0x66, 0x6f, 0x6f, 0, 3, 0, 0, 0, 0x62, 0x61, 0x72, 0
+            ]
+        );
+    }
+
+    #[test]
+    fn should_encode_strings_as_utf8() {
+        let module = parse(r#"func main() { "héllo" }"#, Source::Input).unwrap();
+
+        let bytes = Data::from(&module).into_bytes();
+
+        assert_eq!(bytes[0], 6);
+        assert_eq!(&bytes[4..10], "héllo".as_bytes());
+        assert_eq!(bytes.len(), 12);
+    }
+
+    #[test]
+    fn should_store_repeated_strings_once() {
+        let module = parse(
+            r#"func main() { let a = "foo"; let b = "foo"; 0 }"#,
+            Source::Input,
+        )
+        .unwrap();
+
+        let data = Data::from(&module);
+
+        assert_eq!(data.string("foo"), Some(0));
+        assert_eq!(data.len(), 8);
+    }
+
+    #[test]
+    fn should_lay_out_constant_arrays() {
+        let input = r#"func main() { let a = [[1, 2], ["ab"]]; let b = [a]; 0 }"#;
+        let module = parse(input, Source::Input).unwrap();
+
+        let data = Data::from(&module);
+
+        assert_eq!(data.array(Span::new(22, 38)), Some(28));
+        assert_eq!(data.array(Span::new(23, 29)), Some(0));
+        assert_eq!(data.array(Span::new(48, 51)), None);
+        assert_eq!(data.string("ab"), Some(12));
+
+        let words: Vec<_> = data
+            .into_bytes()
+            .chunks(4)
+            .map(|word| i32::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+
+        assert_eq!(words[..3], [2, 1, 2]);
+        assert_eq!(words[5..], [1, 12, 2, 0, 20]);
+    }
+}
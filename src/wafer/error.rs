@@ -0,0 +1,365 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use super::types::{Type, TypeNames};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum Source {
+    Prelude,
+    Collector,
+    Input,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Span {
+    pub source: Source,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self {
+            source: Source::Input,
+            start,
+            end,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CompileError {
+    Syntax {
+        message: String,
+        span: Span,
+    },
+    UnknownVariable {
+        name: String,
+        span: Span,
+    },
+    UnknownFunction {
+        name: String,
+        span: Span,
+    },
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    DuplicateDefinition {
+        name: String,
+        span: Span,
+    },
+    // The structs and enums in scope are listed along with the built-in types.
+    UnknownType {
+        name: String,
+        declared: Vec<String>,
+        span: Span,
+    },
+    NumberOutOfRange {
+        value: String,
+        r#type: Type,
+        span: Span,
+    },
+    // Types are spelled out by the module's `TypeNames`, since only they know
+    // the names of structs and enums.
+    MismatchedTypes {
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    MissingValue {
+        expected: String,
+        span: Span,
+    },
+    UnexpectedValue {
+        span: Span,
+    },
+    MissingReturn {
+        name: String,
+        r#type: String,
+        span: Span,
+    },
+    OutsideLoop {
+        keyword: String,
+        span: Span,
+    },
+    UseBeforeDeclaration {
+        name: String,
+        span: Span,
+    },
+    OutOfScope {
+        name: String,
+        span: Span,
+    },
+    NotConstant {
+        span: Span,
+    },
+    DivisionByZero {
+        span: Span,
+    },
+    AssignToConstant {
+        name: String,
+        span: Span,
+    },
+    UnknownField {
+        name: String,
+        r#type: String,
+        span: Span,
+    },
+    MissingField {
+        name: String,
+        r#type: String,
+        span: Span,
+    },
+    UnknownVariant {
+        name: String,
+        r#type: String,
+        span: Span,
+    },
+    DuplicateDiscriminant {
+        value: i32,
+        span: Span,
+    },
+    NonExhaustiveMatch {
+        missing: Vec<String>,
+        span: Span,
+    },
+    UnreachablePattern {
+        span: Span,
+    },
+}
+
+impl CompileError {
+    pub fn mismatched_types(expected: Type, found: Type, span: Span, names: &TypeNames) -> Self {
+        CompileError::MismatchedTypes {
+            expected: names.describe(expected),
+            found: names.describe(found),
+            span,
+        }
+    }
+
+    pub fn unknown_type(name: &str, span: Span, names: &TypeNames) -> Self {
+        CompileError::UnknownType {
+            name: name.to_string(),
+            declared: names.iter().map(str::to_string).collect(),
+            span,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            CompileError::Syntax { span, .. }
+            | CompileError::UnknownVariable { span, .. }
+            | CompileError::UnknownFunction { span, .. }
+            | CompileError::ArityMismatch { span, .. }
+            | CompileError::DuplicateDefinition { span, .. }
+            | CompileError::UnknownType { span, .. }
+            | CompileError::NumberOutOfRange { span, .. }
+            | CompileError::MismatchedTypes { span, .. }
+            | CompileError::MissingValue { span, .. }
+            | CompileError::UnexpectedValue { span }
+            | CompileError::MissingReturn { span, .. }
+            | CompileError::OutsideLoop { span, .. }
+            | CompileError::UseBeforeDeclaration { span, .. }
+            | CompileError::OutOfScope { span, .. }
+            | CompileError::NotConstant { span }
+            | CompileError::DivisionByZero { span }
+            | CompileError::AssignToConstant { span, .. }
+            | CompileError::UnknownField { span, .. }
+            | CompileError::MissingField { span, .. }
+            | CompileError::UnknownVariant { span, .. }
+            | CompileError::DuplicateDiscriminant { span, .. }
+            | CompileError::NonExhaustiveMatch { span, .. }
+            | CompileError::UnreachablePattern { span } => *span,
+        }
+    }
+
+    pub fn help(&self) -> Option<String> {
+        match self {
+            CompileError::Syntax { .. } => None,
+            CompileError::UnknownVariable { name, .. } => Some(format!(
+                "declare it with `let {name} = ...;` before using it"
+            )),
+            CompileError::UnknownFunction { name, .. } => Some(format!(
+                "define it with `func {name}(...) {{ ... }}` or declare it with `extern func`"
+            )),
+            CompileError::ArityMismatch { name, expected, .. } => {
+                Some(format!("`{name}` is declared with {expected} parameter(s)"))
+            }
+            CompileError::DuplicateDefinition { .. } => {
+                Some("rename one of the definitions".to_string())
+            }
+            CompileError::UnknownType { declared, .. } => {
+                let builtin = [
+                    "i32",
+                    "u32",
+                    "i64",
+                    "u64",
+                    "f32",
+                    "f64",
+                    "Int32Array",
+                    "Uint16Array",
+                    "Uint8Array",
+                ];
+                let mut types: Vec<_> = builtin
+                    .into_iter()
+                    .chain(declared.iter().map(String::as_str))
+                    .map(|name| format!("`{name}`"))
+                    .collect();
+                let last = types.pop().expect("there are built-in types");
+
+                Some(format!(
+                    "the supported types are {} and {last}",
+                    types.join(", ")
+                ))
+            }
+            CompileError::NumberOutOfRange { value, r#type, .. } => Some(format!(
+                "the literal `{value}` does not fit into the type `{type}`"
+            )),
+            // Only numbers have conversion functions.
+            CompileError::MismatchedTypes { expected, .. } => match Type::from_name(expected) {
+                Some(Type::Array(_)) | None => None,
+                Some(_) => Some(format!("convert the value with `{expected}(...)`")),
+            },
+            CompileError::MissingValue { .. } => None,
+            CompileError::UnexpectedValue { .. } => Some(
+                "the function has no return type; annotate it with `: type` to return a value"
+                    .to_string(),
+            ),
+            CompileError::MissingReturn { r#type, .. } => Some(format!(
+                "end the body with a value of type `{type}` or `return` one on every path"
+            )),
+            CompileError::OutsideLoop { keyword, .. } => {
+                Some(format!("`{keyword}` can only be used inside a loop"))
+            }
+            CompileError::UseBeforeDeclaration { name, .. } => {
+                Some(format!("move the `let {name} = ...;` above its first use"))
+            }
+            CompileError::OutOfScope { name, .. } => Some(format!(
+                "`{name}` is only visible inside the block that declares it"
+            )),
+            CompileError::NotConstant { .. } => Some(
+                "module-level initializers can only use literals, constants and operators"
+                    .to_string(),
+            ),
+            CompileError::DivisionByZero { .. } => {
+                Some("dividing by zero would trap at runtime".to_string())
+            }
+            CompileError::AssignToConstant { name, .. } => Some(format!(
+                "declare it with `let {name} = ...;` to make it mutable"
+            )),
+            // Built-in types have no declaration to add fields to.
+            CompileError::UnknownField { name, r#type, .. } => match Type::from_name(r#type) {
+                Some(_) => Some("only structs have fields".to_string()),
+                None => Some(format!(
+                    "add `{name}` to the declaration of `struct {type}`"
+                )),
+            },
+            CompileError::MissingField { name, .. } => {
+                Some(format!("initialize it with `{name}: ...`"))
+            }
+            CompileError::UnknownVariant { name, r#type, .. } => {
+                Some(format!("add `{name}` to the declaration of `enum {type}`"))
+            }
+            CompileError::DuplicateDiscriminant { .. } => {
+                Some("give each variant a different value".to_string())
+            }
+            CompileError::NonExhaustiveMatch { .. } => {
+                Some("add an arm for each missing pattern, or a `_` arm".to_string())
+            }
+            CompileError::UnreachablePattern { .. } => {
+                Some("an earlier arm already matches every value this one does".to_string())
+            }
+        }
+    }
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Syntax { message, .. } => write!(f, "syntax error: {message}"),
+            CompileError::UnknownVariable { name, .. } => {
+                write!(f, "cannot find variable `{name}` in this scope")
+            }
+            CompileError::UnknownFunction { name, .. } => {
+                write!(f, "cannot find function `{name}`")
+            }
+            CompileError::ArityMismatch {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "function `{name}` takes {expected} argument(s) but {found} were supplied"
+            ),
+            CompileError::DuplicateDefinition { name, .. } => {
+                write!(f, "`{name}` is defined multiple times")
+            }
+            CompileError::UnknownType { name, .. } => write!(f, "cannot find type `{name}`"),
+            CompileError::NumberOutOfRange { r#type, .. } => {
+                write!(f, "literal out of range for `{type}`")
+            }
+            CompileError::MismatchedTypes {
+                expected, found, ..
+            } => write!(
+                f,
+                "mismatched types: expected `{expected}`, found `{found}`"
+            ),
+            CompileError::MissingValue { expected, .. } => {
+                write!(f, "mismatched types: expected `{expected}`, found `()`")
+            }
+            CompileError::UnexpectedValue { .. } => {
+                write!(f, "mismatched types: expected `()`, found a value")
+            }
+            CompileError::MissingReturn { name, .. } => {
+                write!(f, "function `{name}` does not return a value on every path")
+            }
+            CompileError::OutsideLoop { keyword, .. } => {
+                write!(f, "`{keyword}` outside of a loop")
+            }
+            CompileError::UseBeforeDeclaration { name, .. } => {
+                write!(f, "cannot use variable `{name}` before its declaration")
+            }
+            CompileError::OutOfScope { name, .. } => {
+                write!(f, "variable `{name}` is no longer in scope")
+            }
+            CompileError::NotConstant { .. } => {
+                write!(f, "expression cannot be evaluated at compile time")
+            }
+            CompileError::DivisionByZero { .. } => write!(f, "attempt to divide by zero"),
+            CompileError::AssignToConstant { name, .. } => {
+                write!(f, "cannot assign to constant `{name}`")
+            }
+            CompileError::UnknownField { name, r#type, .. } => {
+                write!(f, "no field `{name}` on type `{type}`")
+            }
+            CompileError::MissingField { name, r#type, .. } => {
+                write!(f, "missing field `{name}` in initializer of `{type}`")
+            }
+            CompileError::UnknownVariant { name, r#type, .. } => {
+                write!(f, "no variant `{name}` in enum `{type}`")
+            }
+            CompileError::DuplicateDiscriminant { value, .. } => {
+                write!(f, "discriminant `{value}` is assigned more than once")
+            }
+            CompileError::NonExhaustiveMatch { missing, .. } => {
+                let missing: Vec<_> = missing
+                    .iter()
+                    .map(|pattern| format!("`{pattern}`"))
+                    .collect();
+
+                write!(
+                    f,
+                    "non-exhaustive patterns: {} not covered",
+                    missing.join(", ")
+                )
+            }
+            CompileError::UnreachablePattern { .. } => write!(f, "unreachable pattern"),
+        }
+    }
+}
+
+impl Error for CompileError {}
@@ -0,0 +1,1187 @@
+use std::iter::Peekable;
+use std::str::FromStr;
+
+use pest::Parser as PestParser;
+use pest::error::{Error as PestError, InputLocation};
+use pest::iterators::{Pair, Pairs};
+
+use super::ast::{
+    Arm, BinaryOperator, Block, Enum, Expr, ExprKind, Function, Global, Identifier, Import,
+    Iterable, Module, Parameter, Pattern, Stmt, StmtKind, Struct, UnaryOperator, Variant,
+};
+use super::types::Type;
+use super::{CompileError, Source, Span};
+
+#[derive(pest_derive::Parser)]
+#[grammar = "src/wafer.pest"]
+struct Parser;
+
+pub fn parse(input: &str, source: Source) -> Result<Module, CompileError> {
+    let pair = Parser::parse(Rule::module, input)
+        .map_err(|error| syntax_error(error, source))?
+        .next()
+        .unwrap();
+
+    let builder = Builder { source };
+
+    // The grammar can't tell surrogates and values past U+10FFFF apart from
+    // other `\u{...}` escapes, nor numbers too large for any type from other
+    // numbers, so they are rejected before building the AST. Numbers that are
+    // only too large for their type are reported by the checker.
+    for pair in pair.clone().into_inner().flatten() {
+        let span = builder.span(&pair);
+
+        return Err(match pair.as_rule() {
+            Rule::unicode_scalar if unicode_scalar(&pair).is_none() => CompileError::Syntax {
+                message: "invalid unicode escape".to_string(),
+                span,
+            },
+            // Such numbers may not even fit into an `i128`, so they are
+            // reported as written.
+            Rule::number if number(&pair).is_none() => CompileError::NumberOutOfRange {
+                value: pair.as_str().to_string(),
+                r#type: Type::U64,
+                span,
+            },
+            _ => continue,
+        });
+    }
+
+    Ok(builder.module(pair))
+}
+
+fn unicode_scalar(pair: &Pair<Rule>) -> Option<char> {
+    u32::from_str_radix(pair.as_str(), 16)
+        .ok()
+        .and_then(char::from_u32)
+}
+
+// Hexadecimal and binary literals are written with a `0x` and `0b` prefix.
+// Every literal has to fit into a `u64`, the widest type.
+fn number(pair: &Pair<Rule>) -> Option<i128> {
+    let literal = pair.as_str();
+    let number = if let Some(digits) = literal.strip_prefix("0x") {
+        i128::from_str_radix(digits, 16)
+    } else if let Some(digits) = literal.strip_prefix("0b") {
+        i128::from_str_radix(digits, 2)
+    } else {
+        i128::from_str(literal)
+    };
+
+    number.ok().filter(|number| u64::try_from(*number).is_ok())
+}
+
+fn syntax_error(error: PestError<Rule>, source: Source) -> CompileError {
+    let (start, end) = match error.location {
+        InputLocation::Pos(pos) => (pos, pos),
+        InputLocation::Span(span) => span,
+    };
+
+    CompileError::Syntax {
+        message: error.variant.message().into_owned(),
+        span: Span { source, start, end },
+    }
+}
+
+struct Builder {
+    source: Source,
+}
+
+impl Builder {
+    fn span(&self, pair: &Pair<Rule>) -> Span {
+        let span = pair.as_span();
+
+        Span {
+            source: self.source,
+            start: span.start(),
+            end: span.end(),
+        }
+    }
+
+    fn identifier(&self, pair: Pair<Rule>) -> Identifier {
+        Identifier {
+            name: pair.as_str().to_string(),
+            span: self.span(&pair),
+        }
+    }
+
+    fn module(&self, pair: Pair<Rule>) -> Module {
+        let mut module = Module::default();
+
+        for pair in pair.into_inner() {
+            match pair.as_rule() {
+                Rule::function => module.functions.push(self.function(pair, false)),
+                Rule::public_function => module
+                    .functions
+                    .push(self.function(pair.into_inner().next().unwrap(), true)),
+                Rule::external_function => module.imports.push(self.import(pair)),
+                Rule::struct_definition => module.structs.push(self.r#struct(pair)),
+                Rule::enum_definition => module.enums.push(self.r#enum(pair)),
+                Rule::global => module.globals.push(self.global(pair, false)),
+                Rule::public_global => module
+                    .globals
+                    .push(self.global(pair.into_inner().next().unwrap(), true)),
+                Rule::EOI => (),
+                _ => unreachable!(),
+            }
+        }
+
+        module
+    }
+
+    // Precedence climbing: folds every operator binding at least as tightly
+    // as `minimum` into `left`. Operators of equal precedence associate to the
+    // left, since the right operand only takes tighter ones.
+    fn binary_expression(
+        &self,
+        mut left: Expr,
+        pairs: &mut Peekable<Pairs<Rule>>,
+        minimum: u8,
+    ) -> Expr {
+        while let Some(operator) =
+            pairs.next_if(|pair| precedence(binary_operator(pair.as_str())) >= minimum)
+        {
+            let operator = binary_operator(operator.as_str());
+            let right = self.expression(pairs.next().unwrap());
+            let right = self.binary_expression(right, pairs, precedence(operator) + 1);
+            let span = Span {
+                end: right.span.end,
+                ..left.span
+            };
+
+            left = Expr {
+                kind: ExprKind::Binary {
+                    operator,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+                span,
+            };
+        }
+
+        left
+    }
+
+    fn type_annotation(&self, pairs: &mut Peekable<Pairs<Rule>>) -> Option<Identifier> {
+        pairs
+            .next_if(|pair| pair.as_rule() == Rule::type_annotation)
+            .map(|pair| self.identifier(pair.into_inner().next().unwrap()))
+    }
+
+    fn function(&self, pair: Pair<Rule>, public: bool) -> Function {
+        let mut pairs = pair.into_inner().peekable();
+
+        Function {
+            name: self.identifier(pairs.next().unwrap()),
+            public,
+            parameters: self.parameters(pairs.next().unwrap()),
+            returns: self.type_annotation(&mut pairs),
+            body: self.block(pairs.next().unwrap()),
+        }
+    }
+
+    fn import(&self, pair: Pair<Rule>) -> Import {
+        let mut pairs = pair.into_inner().peekable();
+
+        Import {
+            name: self.identifier(pairs.next().unwrap()),
+            parameters: self.parameters(pairs.next().unwrap()),
+            returns: self.type_annotation(&mut pairs),
+        }
+    }
+
+    fn global(&self, pair: Pair<Rule>, public: bool) -> Global {
+        let mut pairs = pair.into_inner().peekable();
+
+        Global {
+            constant: pairs.next().unwrap().as_str() == "const",
+            name: self.identifier(pairs.next().unwrap()),
+            public,
+            r#type: self.type_annotation(&mut pairs),
+            value: self.expression(pairs.next().unwrap()),
+        }
+    }
+
+    fn r#struct(&self, pair: Pair<Rule>) -> Struct {
+        let mut pairs = pair.into_inner();
+
+        Struct {
+            name: self.identifier(pairs.next().unwrap()),
+            fields: pairs.map(|pair| self.parameter(pair)).collect(),
+        }
+    }
+
+    fn r#enum(&self, pair: Pair<Rule>) -> Enum {
+        let mut pairs = pair.into_inner();
+
+        Enum {
+            name: self.identifier(pairs.next().unwrap()),
+            variants: pairs
+                .map(|pair| {
+                    let mut pairs = pair.into_inner();
+
+                    Variant {
+                        name: self.identifier(pairs.next().unwrap()),
+                        discriminant: pairs.next().map(|pair| self.expression(pair)),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn arm(&self, pair: Pair<Rule>) -> Arm {
+        let mut pairs = pair.into_inner();
+        let pattern = pairs.next().unwrap();
+        let pattern = match pattern.as_rule() {
+            Rule::wildcard => Pattern::Wildcard(self.span(&pattern)),
+            _ => Pattern::Value(self.expression(pattern)),
+        };
+
+        let body = pairs.next().unwrap();
+        let body = match body.as_rule() {
+            Rule::block_expression => self.block(body),
+            _ => {
+                let expression = self.expression(body);
+
+                Block {
+                    statements: vec![],
+                    span: expression.span,
+                    result: Some(Box::new(expression)),
+                }
+            }
+        };
+
+        Arm { pattern, body }
+    }
+
+    fn parameters(&self, pair: Pair<Rule>) -> Vec<Parameter> {
+        pair.into_inner().map(|pair| self.parameter(pair)).collect()
+    }
+
+    fn parameter(&self, pair: Pair<Rule>) -> Parameter {
+        let mut pairs = pair.into_inner().peekable();
+
+        Parameter {
+            name: self.identifier(pairs.next().unwrap()),
+            r#type: self.type_annotation(&mut pairs),
+        }
+    }
+
+    // `a.b.c` is built as `(a.b).c`.
+    // Each index or field applies to everything before it, so `grid[y][x]`
+    // reads an array of arrays and `h.arr[9]` an array held in a field.
+    fn access(&self, pair: Pair<Rule>) -> Expr {
+        let mut pairs = pair.into_inner();
+        let first = self.identifier(pairs.next().unwrap());
+        let mut expression = Expr {
+            span: first.span,
+            kind: ExprKind::Variable(first),
+        };
+
+        for pair in pairs {
+            let span = Span {
+                end: self.span(&pair).end,
+                ..expression.span
+            };
+            let object = Box::new(expression);
+            let inner = pair.as_rule();
+            let next = pair.into_inner().next().unwrap();
+
+            expression = Expr {
+                kind: match inner {
+                    Rule::index => ExprKind::Index {
+                        array: object,
+                        index: Box::new(self.expression(next)),
+                    },
+                    Rule::field => ExprKind::Field {
+                        object,
+                        field: self.identifier(next),
+                    },
+                    _ => unreachable!("{:#?}", next),
+                },
+                span,
+            };
+        }
+
+        expression
+    }
+
+    fn block(&self, pair: Pair<Rule>) -> Block {
+        let span = self.span(&pair);
+        let mut statements = vec![];
+        let mut result = None;
+
+        for pair in pair.into_inner() {
+            match pair.as_rule() {
+                Rule::let_statement
+                | Rule::if_statement
+                | Rule::while_statement
+                | Rule::for_statement
+                | Rule::return_statement
+                | Rule::break_statement
+                | Rule::continue_statement
+                | Rule::expression_statement => statements.push(self.statement(pair)),
+                _ => result = Some(Box::new(self.expression(pair))),
+            }
+        }
+
+        Block {
+            statements,
+            result,
+            span,
+        }
+    }
+
+    fn statement(&self, pair: Pair<Rule>) -> Stmt {
+        let span = self.span(&pair);
+
+        let kind = match pair.as_rule() {
+            Rule::let_statement => {
+                let mut pairs = pair.into_inner().peekable();
+
+                StmtKind::Let {
+                    name: self.identifier(pairs.next().unwrap()),
+                    r#type: self.type_annotation(&mut pairs),
+                    value: self.expression(pairs.next().unwrap()),
+                }
+            }
+            Rule::if_statement => {
+                let mut pairs = pair.into_inner();
+
+                let condition = self.expression(pairs.next().unwrap());
+                let then_block = self.block(pairs.next().unwrap());
+                let else_block = pairs.next().map(|pair| match pair.as_rule() {
+                    Rule::if_statement => {
+                        let statement = self.statement(pair);
+
+                        Block {
+                            span: statement.span,
+                            statements: vec![statement],
+                            result: None,
+                        }
+                    }
+                    _ => self.block(pair),
+                });
+
+                StmtKind::If {
+                    condition,
+                    then_block,
+                    else_block,
+                }
+            }
+            Rule::while_statement => {
+                let mut pairs = pair.into_inner();
+
+                StmtKind::While {
+                    condition: self.expression(pairs.next().unwrap()),
+                    body: self.block(pairs.next().unwrap()),
+                }
+            }
+            Rule::return_statement => {
+                let mut pairs = pair.into_inner().skip(1);
+
+                StmtKind::Return(pairs.next().map(|pair| self.expression(pair)))
+            }
+            Rule::for_statement => {
+                let mut pairs = pair.into_inner();
+
+                let variable = self.identifier(pairs.next().unwrap());
+                let iterable = pairs.next().unwrap();
+                let iterable = match iterable.as_rule() {
+                    Rule::range => {
+                        let mut bounds = iterable.into_inner();
+
+                        Iterable::Range {
+                            start: self.expression(bounds.next().unwrap()),
+                            end: self.expression(bounds.next().unwrap()),
+                        }
+                    }
+                    _ => Iterable::Array(self.expression(iterable)),
+                };
+
+                StmtKind::For {
+                    variable,
+                    iterable,
+                    body: self.block(pairs.next().unwrap()),
+                }
+            }
+            Rule::break_statement => StmtKind::Break,
+            Rule::continue_statement => StmtKind::Continue,
+            Rule::expression_statement => {
+                StmtKind::Expr(self.expression(pair.into_inner().next().unwrap()))
+            }
+            _ => unreachable!("{:#?}", pair),
+        };
+
+        Stmt { kind, span }
+    }
+
+    fn expression(&self, pair: Pair<Rule>) -> Expr {
+        let span = self.span(&pair);
+
+        let kind = match pair.as_rule() {
+            Rule::binary_expression => {
+                let mut pairs = pair.into_inner().peekable();
+                let left = self.expression(pairs.next().unwrap());
+
+                return self.binary_expression(left, &mut pairs, 0);
+            }
+            Rule::unary_expression => {
+                let mut pairs = pair.into_inner();
+                let operator = unary_operator(pairs.next().unwrap().as_str());
+                let Expr {
+                    kind,
+                    span: operand_span,
+                } = self.expression(pairs.next().unwrap());
+
+                // Negative literals are folded, so that the most negative
+                // numbers can be written even though their magnitude is out of
+                // range.
+                match (operator, kind) {
+                    (UnaryOperator::Negate, ExprKind::Number(number)) => ExprKind::Number(-number),
+                    (UnaryOperator::Negate, ExprKind::Float(number)) => ExprKind::Float(-number),
+                    (operator, kind) => ExprKind::Unary {
+                        operator,
+                        operand: Box::new(Expr {
+                            kind,
+                            span: operand_span,
+                        }),
+                    },
+                }
+            }
+            Rule::variable_assignment_expression => {
+                let mut pairs = pair.into_inner();
+
+                ExprKind::Assign {
+                    target: self.identifier(pairs.next().unwrap()),
+                    value: Box::new(self.expression(pairs.next().unwrap())),
+                }
+            }
+            Rule::access_assignment_expression => {
+                let mut pairs = pair.into_inner();
+                let target = self.access(pairs.next().unwrap());
+                let value = Box::new(self.expression(pairs.next().unwrap()));
+
+                match target.kind {
+                    ExprKind::Index { array, index } => ExprKind::IndexAssign {
+                        array,
+                        index,
+                        value,
+                    },
+                    ExprKind::Field { object, field } => ExprKind::FieldAssign {
+                        object,
+                        field,
+                        value,
+                    },
+                    _ => unreachable!("accesses have at least one index or field"),
+                }
+            }
+            Rule::struct_literal => {
+                let mut pairs = pair.into_inner();
+
+                ExprKind::Struct {
+                    name: self.identifier(pairs.next().unwrap()),
+                    fields: pairs
+                        .map(|pair| {
+                            let mut pairs = pair.into_inner();
+
+                            (
+                                self.identifier(pairs.next().unwrap()),
+                                self.expression(pairs.next().unwrap()),
+                            )
+                        })
+                        .collect(),
+                }
+            }
+            Rule::call_expression => {
+                let mut pairs = pair.into_inner();
+
+                ExprKind::Call {
+                    function: self.identifier(pairs.next().unwrap()),
+                    arguments: pairs
+                        .next()
+                        .unwrap()
+                        .into_inner()
+                        .map(|pair| self.expression(pair))
+                        .collect(),
+                }
+            }
+            Rule::if_expression => {
+                let mut pairs = pair.into_inner();
+
+                let condition = self.expression(pairs.next().unwrap());
+                let then_block = self.block(pairs.next().unwrap());
+
+                let else_pair = pairs.next().unwrap();
+                let else_block = match else_pair.as_rule() {
+                    Rule::if_expression => {
+                        let expression = self.expression(else_pair);
+
+                        Block {
+                            statements: vec![],
+                            span: expression.span,
+                            result: Some(Box::new(expression)),
+                        }
+                    }
+                    _ => self.block(else_pair),
+                };
+
+                ExprKind::If {
+                    condition: Box::new(condition),
+                    then_block,
+                    else_block,
+                }
+            }
+            Rule::access => return self.access(pair),
+            Rule::enum_variant => {
+                let mut pairs = pair.into_inner();
+
+                ExprKind::Variant {
+                    r#enum: self.identifier(pairs.next().unwrap()),
+                    variant: self.identifier(pairs.next().unwrap()),
+                }
+            }
+            Rule::match_expression => {
+                let mut pairs = pair.into_inner().skip(1);
+
+                ExprKind::Match {
+                    value: Box::new(self.expression(pairs.next().unwrap())),
+                    arms: pairs.map(|pair| self.arm(pair)).collect(),
+                }
+            }
+            Rule::array_literal => ExprKind::Array(
+                pair.into_inner()
+                    .map(|pair| self.expression(pair))
+                    .collect(),
+            ),
+            Rule::identifier => ExprKind::Variable(self.identifier(pair)),
+            Rule::number => {
+                ExprKind::Number(number(&pair).expect("numbers are validated before building"))
+            }
+            Rule::negative_number => {
+                let magnitude = pair.into_inner().next().unwrap();
+
+                ExprKind::Number(
+                    -number(&magnitude).expect("numbers are validated before building"),
+                )
+            }
+            Rule::float => {
+                ExprKind::Float(f64::from_str(pair.as_str()).expect("failed to parse float"))
+            }
+            Rule::string_literal => ExprKind::String(string(pair)),
+            _ => unreachable!("{:#?}", pair),
+        };
+
+        Expr { kind, span }
+    }
+}
+
+fn string(pair: Pair<Rule>) -> String {
+    let mut value = String::new();
+
+    for part in pair.into_inner() {
+        match part.as_rule() {
+            Rule::string_characters => value.push_str(part.as_str()),
+            Rule::escape => {
+                let escape = part.into_inner().next().unwrap();
+
+                value.push(match (escape.as_rule(), escape.as_str()) {
+                    (Rule::unicode_scalar, _) => {
+                        unicode_scalar(&escape).expect("escapes are validated before building")
+                    }
+                    (_, "n") => '\n',
+                    (_, "r") => '\r',
+                    (_, "t") => '\t',
+                    (_, "0") => '\0',
+                    (_, character) => character.chars().next().unwrap(),
+                });
+            }
+            _ => unreachable!("{:#?}", part),
+        }
+    }
+
+    value
+}
+
+// Binding strength of each operator, following C: a higher number binds
+// tighter.
+fn precedence(operator: BinaryOperator) -> u8 {
+    match operator {
+        BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 10,
+        BinaryOperator::Add | BinaryOperator::Subtract => 9,
+        BinaryOperator::ShiftLeft
+        | BinaryOperator::ShiftRight
+        | BinaryOperator::ShiftRightUnsigned => 8,
+        BinaryOperator::LessThan
+        | BinaryOperator::GreaterThan
+        | BinaryOperator::LessThanOrEqual
+        | BinaryOperator::GreaterThanOrEqual => 7,
+        BinaryOperator::Equal | BinaryOperator::NotEqual => 6,
+        BinaryOperator::And => 5,
+        BinaryOperator::Xor => 4,
+        BinaryOperator::Or => 3,
+        BinaryOperator::LogicalAnd => 2,
+        BinaryOperator::LogicalOr => 1,
+    }
+}
+
+fn binary_operator(operator: &str) -> BinaryOperator {
+    match operator {
+        "+" => BinaryOperator::Add,
+        "-" => BinaryOperator::Subtract,
+        "*" => BinaryOperator::Multiply,
+        "/" => BinaryOperator::Divide,
+        "==" => BinaryOperator::Equal,
+        "!=" => BinaryOperator::NotEqual,
+        "<=" => BinaryOperator::LessThanOrEqual,
+        "<" => BinaryOperator::LessThan,
+        ">=" => BinaryOperator::GreaterThanOrEqual,
+        ">" => BinaryOperator::GreaterThan,
+        "%" => BinaryOperator::Remainder,
+        "&" => BinaryOperator::And,
+        "|" => BinaryOperator::Or,
+        "&&" | "and" => BinaryOperator::LogicalAnd,
+        "||" | "or" => BinaryOperator::LogicalOr,
+        "^" => BinaryOperator::Xor,
+        "<<" => BinaryOperator::ShiftLeft,
+        ">>" => BinaryOperator::ShiftRight,
+        ">>>" => BinaryOperator::ShiftRightUnsigned,
+        _ => unreachable!(),
+    }
+}
+
+fn unary_operator(operator: &str) -> UnaryOperator {
+    match operator {
+        "-" => UnaryOperator::Negate,
+        "!" | "not" => UnaryOperator::Not,
+        "~" => UnaryOperator::Complement,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wafer::ast::{
+        BinaryOperator, Expr, ExprKind, Identifier, Iterable, Pattern, StmtKind, UnaryOperator,
+    };
+    use crate::wafer::types::Type;
+    use crate::wafer::{CompileError, Source, Span};
+
+    use super::parse;
+
+    #[test]
+    fn should_build_float_literals() {
+        let module = parse("func f() { 1.5 + 2e3 + 2.5E-1 }", Source::Input).unwrap();
+        let mut literals = vec![];
+        let mut expr = module.functions[0].body.result.as_deref().unwrap();
+
+        while let ExprKind::Binary { left, right, .. } = &expr.kind {
+            literals.push(right.kind.clone());
+            expr = left;
+        }
+        literals.push(expr.kind.clone());
+
+        assert_eq!(
+            literals,
+            vec![
+                ExprKind::Float(0.25),
+                ExprKind::Float(2000.0),
+                ExprKind::Float(1.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_unescape_string_literals() {
+        let module = parse(
+            r#"func f() { "say \"hi\"\n\\ \u{1F600}\u{e9}" }"#,
+            Source::Input,
+        )
+        .unwrap();
+        let result = module.functions[0].body.result.as_deref().unwrap();
+
+        assert_eq!(
+            result.kind,
+            ExprKind::String("say \"hi\"\n\\ \u{1F600}\u{e9}".to_string())
+        );
+    }
+
+    #[test]
+    fn should_build_hexadecimal_and_binary_literals() {
+        let module = parse(
+            "func f() { 0xFF + 0b1010 + 0xFFFFFFFFFFFFFFFF }",
+            Source::Input,
+        )
+        .unwrap();
+        let mut literals = vec![];
+        let mut expr = module.functions[0].body.result.as_deref().unwrap();
+
+        while let ExprKind::Binary { left, right, .. } = &expr.kind {
+            literals.push(right.kind.clone());
+            expr = left;
+        }
+        literals.push(expr.kind.clone());
+
+        assert_eq!(
+            literals,
+            vec![
+                ExprKind::Number(u64::MAX.into()),
+                ExprKind::Number(10),
+                ExprKind::Number(255)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_numbers_too_large_for_any_type() {
+        assert_eq!(
+            parse("func f() { 1 + 0x10000000000000000 }", Source::Input),
+            Err(CompileError::NumberOutOfRange {
+                value: "0x10000000000000000".to_string(),
+                r#type: Type::U64,
+                span: Span::new(15, 34),
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_escapes() {
+        assert!(matches!(
+            parse(r#"func f() { "\q" }"#, Source::Input),
+            Err(CompileError::Syntax { .. })
+        ));
+        assert_eq!(
+            parse(r#"func f() { "\u{D800}" }"#, Source::Input),
+            Err(CompileError::Syntax {
+                message: "invalid unicode escape".to_string(),
+                span: Span::new(15, 19),
+            })
+        );
+    }
+
+    #[test]
+    fn should_build_left_associative_binary_expressions() {
+        let module = parse("func f() { 1 - 2 - 3 }", Source::Input).unwrap();
+        let result = module.functions[0].body.result.as_deref().unwrap();
+
+        let ExprKind::Binary {
+            operator: BinaryOperator::Subtract,
+            left,
+            right,
+        } = &result.kind
+        else {
+            panic!("expected subtraction, got {result:?}");
+        };
+
+        assert!(matches!(
+            left.kind,
+            ExprKind::Binary {
+                operator: BinaryOperator::Subtract,
+                ..
+            }
+        ));
+        assert_eq!(right.kind, ExprKind::Number(3));
+        assert_eq!(result.span, Span::new(11, 20));
+    }
+
+    #[test]
+    fn should_build_binary_expressions_by_precedence() {
+        let module = parse("func f() { 1 + 2 * 3 == 7 }", Source::Input).unwrap();
+        let result = module.functions[0].body.result.as_deref().unwrap();
+
+        let ExprKind::Binary {
+            operator: BinaryOperator::Equal,
+            left,
+            right,
+        } = &result.kind
+        else {
+            panic!("expected comparison, got {result:?}");
+        };
+        let ExprKind::Binary {
+            operator: BinaryOperator::Add,
+            right: product,
+            ..
+        } = &left.kind
+        else {
+            panic!("expected addition, got {left:?}");
+        };
+
+        assert!(matches!(
+            product.kind,
+            ExprKind::Binary {
+                operator: BinaryOperator::Multiply,
+                ..
+            }
+        ));
+        assert_eq!(product.span, Span::new(15, 20));
+        assert_eq!(left.span, Span::new(11, 20));
+        assert_eq!(right.kind, ExprKind::Number(7));
+        assert_eq!(result.span, Span::new(11, 25));
+    }
+
+    #[test]
+    fn should_build_unary_expressions_and_negative_literals() {
+        let module = parse("func f() { -2147483648 >>> ~x }", Source::Input).unwrap();
+        let result = module.functions[0].body.result.as_deref().unwrap();
+
+        let ExprKind::Binary {
+            operator: BinaryOperator::ShiftRightUnsigned,
+            left,
+            right,
+        } = &result.kind
+        else {
+            panic!("expected shift, got {result:?}");
+        };
+
+        assert_eq!(left.kind, ExprKind::Number(-2147483648));
+        assert_eq!(left.span, Span::new(11, 22));
+        assert!(matches!(
+            right.kind,
+            ExprKind::Unary {
+                operator: UnaryOperator::Complement,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn should_build_return_statements() {
+        let module = parse("func f() { return; return 1; returnValue; }", Source::Input).unwrap();
+        let body = &module.functions[0].body;
+
+        assert!(body.result.is_none());
+        assert!(matches!(body.statements[0].kind, StmtKind::Return(None)));
+        assert!(matches!(body.statements[1].kind, StmtKind::Return(Some(_))));
+        assert!(matches!(body.statements[2].kind, StmtKind::Expr(_)));
+    }
+
+    #[test]
+    fn should_build_for_statements() {
+        let module = parse(
+            "func f(a) { for i in 0..a { } for x in a { } 0 }",
+            Source::Input,
+        )
+        .unwrap();
+        let statements = &module.functions[0].body.statements;
+
+        let StmtKind::For {
+            variable,
+            iterable: Iterable::Range { start, end },
+            ..
+        } = &statements[0].kind
+        else {
+            panic!("expected range loop");
+        };
+
+        assert_eq!(variable.name, "i");
+        assert_eq!(start.kind, ExprKind::Number(0));
+        assert_eq!(end.span, Span::new(24, 25));
+
+        assert!(matches!(
+            &statements[1].kind,
+            StmtKind::For {
+                iterable: Iterable::Array(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn should_build_loop_control_statements() {
+        let module = parse("func f() { break; continue; breaker; }", Source::Input).unwrap();
+        let body = &module.functions[0].body;
+
+        assert!(matches!(body.statements[0].kind, StmtKind::Break));
+        assert!(matches!(body.statements[1].kind, StmtKind::Continue));
+        assert!(matches!(body.statements[2].kind, StmtKind::Expr(_)));
+    }
+
+    #[test]
+    fn should_build_statements() {
+        let module = parse(
+            "func f(a) { let x = a; while x { x := x - 1; } x }",
+            Source::Input,
+        )
+        .unwrap();
+        let function = &module.functions[0];
+
+        assert_eq!(function.name.name, "f");
+        assert_eq!(function.parameters[0].name.name, "a");
+        assert_eq!(function.body.statements.len(), 2);
+
+        let StmtKind::Let { name, value, .. } = &function.body.statements[0].kind else {
+            panic!("expected let statement");
+        };
+
+        assert_eq!(name.name, "x");
+        assert_eq!(
+            value,
+            &Expr {
+                kind: ExprKind::Variable(Identifier {
+                    name: "a".to_string(),
+                    span: Span::new(20, 21),
+                }),
+                span: Span::new(20, 21),
+            }
+        );
+        assert!(matches!(
+            function.body.statements[1].kind,
+            StmtKind::While { .. }
+        ));
+    }
+
+    #[test]
+    fn should_build_type_annotations() {
+        let module = parse(
+            "extern func now(): i64; func f(a: i64, b): i32 { let x: i64 = a; 0 }",
+            Source::Input,
+        )
+        .unwrap();
+        let function = &module.functions[0];
+
+        let annotation = |identifier: Option<&Identifier>| identifier.map(|i| i.name.clone());
+
+        assert_eq!(
+            annotation(module.imports[0].returns.as_ref()),
+            Some("i64".to_string())
+        );
+        assert_eq!(
+            annotation(function.parameters[0].r#type.as_ref()),
+            Some("i64".to_string())
+        );
+        assert_eq!(annotation(function.parameters[1].r#type.as_ref()), None);
+        assert_eq!(
+            annotation(function.returns.as_ref()),
+            Some("i32".to_string())
+        );
+
+        let StmtKind::Let { r#type, .. } = &function.body.statements[0].kind else {
+            panic!("expected let statement");
+        };
+
+        assert_eq!(annotation(r#type.as_ref()), Some("i64".to_string()));
+    }
+
+    #[test]
+    fn should_build_globals() {
+        let module = parse(
+            "let counter = 0; public const WIDTH: i64 = 256; func f() { counter }",
+            Source::Input,
+        )
+        .unwrap();
+
+        assert_eq!(module.globals.len(), 2);
+        assert_eq!(module.functions.len(), 1);
+
+        let counter = &module.globals[0];
+        assert_eq!(counter.name.name, "counter");
+        assert!(!counter.public);
+        assert!(!counter.constant);
+        assert_eq!(counter.value.kind, ExprKind::Number(0));
+
+        let width = &module.globals[1];
+        assert_eq!(width.name.name, "WIDTH");
+        assert!(width.public);
+        assert!(width.constant);
+        assert_eq!(
+            width.r#type.as_ref().map(|r#type| r#type.name.as_str()),
+            Some("i64")
+        );
+    }
+
+    #[test]
+    fn should_build_structs() {
+        let module = parse(
+            "struct P { x, y: f64 } func f() { let p = P { x: 1, y: 2.0 }; p.x := p.x + 1; p.y }",
+            Source::Input,
+        )
+        .unwrap();
+
+        let r#struct = &module.structs[0];
+        assert_eq!(r#struct.name.name, "P");
+        assert_eq!(r#struct.fields.len(), 2);
+        assert_eq!(
+            r#struct.fields[1]
+                .r#type
+                .as_ref()
+                .map(|r#type| r#type.name.as_str()),
+            Some("f64")
+        );
+
+        let body = &module.functions[0].body;
+        let StmtKind::Let { value, .. } = &body.statements[0].kind else {
+            panic!("expected let statement");
+        };
+        let ExprKind::Struct { name, fields } = &value.kind else {
+            panic!("expected struct literal");
+        };
+
+        assert_eq!(name.name, "P");
+        assert_eq!(fields[0].0.name, "x");
+        assert_eq!(fields[1].1.kind, ExprKind::Float(2.0));
+
+        let StmtKind::Expr(assignment) = &body.statements[1].kind else {
+            panic!("expected expression statement");
+        };
+        assert!(matches!(
+            &assignment.kind,
+            ExprKind::FieldAssign { field, .. } if field.name == "x"
+        ));
+
+        let result = body.result.as_deref().unwrap();
+        let ExprKind::Field { object, field } = &result.kind else {
+            panic!("expected field access");
+        };
+
+        assert_eq!(field.name, "y");
+        assert!(matches!(object.kind, ExprKind::Variable(_)));
+        assert_eq!(result.span, Span::new(78, 81));
+    }
+
+    #[test]
+    fn should_build_array_literals_and_nested_indices() {
+        let module = parse(
+            "func f() { let a = [[1], []]; a[0][1] := a[1][0] }",
+            Source::Input,
+        )
+        .unwrap();
+        let body = &module.functions[0].body;
+
+        let StmtKind::Let { value, .. } = &body.statements[0].kind else {
+            panic!("expected let statement");
+        };
+        let ExprKind::Array(elements) = &value.kind else {
+            panic!("expected array literal");
+        };
+
+        assert_eq!(elements.len(), 2);
+        assert_eq!(
+            elements[0].kind,
+            ExprKind::Array(vec![Expr {
+                kind: ExprKind::Number(1),
+                span: Span::new(21, 22),
+            }])
+        );
+        assert_eq!(elements[1].kind, ExprKind::Array(vec![]));
+
+        let result = body.result.as_deref().unwrap();
+        let ExprKind::IndexAssign {
+            array,
+            index,
+            value,
+        } = &result.kind
+        else {
+            panic!("expected index assignment");
+        };
+
+        assert_eq!(array.span, Span::new(30, 34));
+        assert!(matches!(array.kind, ExprKind::Index { .. }));
+        assert_eq!(index.kind, ExprKind::Number(1));
+        assert_eq!(value.span, Span::new(41, 48));
+    }
+
+    #[test]
+    fn should_build_indices_of_fields() {
+        let module = parse("func f(h: H) { h.arr[9] := h.rows[0].x }", Source::Input).unwrap();
+
+        let result = module.functions[0].body.result.as_deref().unwrap();
+        let ExprKind::IndexAssign { array, value, .. } = &result.kind else {
+            panic!("expected index assignment");
+        };
+
+        assert!(matches!(&array.kind, ExprKind::Field { field, .. } if field.name == "arr"));
+        assert_eq!(array.span, Span::new(15, 20));
+
+        let ExprKind::Field { object, field } = &value.kind else {
+            panic!("expected field access");
+        };
+
+        assert_eq!(field.name, "x");
+        assert!(matches!(object.kind, ExprKind::Index { .. }));
+        assert_eq!(value.span, Span::new(27, 38));
+    }
+
+    #[test]
+    fn should_build_enums_and_matches() {
+        let module = parse(
+            "enum S { A, B = 2 } func f(s: S) { match s { S::A => 1, _ => { 2 } } }",
+            Source::Input,
+        )
+        .unwrap();
+
+        let r#enum = &module.enums[0];
+        assert_eq!(r#enum.name.name, "S");
+        assert!(r#enum.variants[0].discriminant.is_none());
+        assert_eq!(
+            r#enum.variants[1]
+                .discriminant
+                .as_ref()
+                .map(|value| &value.kind),
+            Some(&ExprKind::Number(2))
+        );
+
+        let result = module.functions[0].body.result.as_deref().unwrap();
+        let ExprKind::Match { value, arms } = &result.kind else {
+            panic!("expected match expression");
+        };
+
+        assert!(matches!(value.kind, ExprKind::Variable(_)));
+        assert_eq!(result.span, Span::new(35, 68));
+
+        let Pattern::Value(pattern) = &arms[0].pattern else {
+            panic!("expected value pattern");
+        };
+
+        assert!(matches!(
+            &pattern.kind,
+            ExprKind::Variant { r#enum, variant } if r#enum.name == "S" && variant.name == "A"
+        ));
+        assert_eq!(pattern.span, Span::new(45, 49));
+        assert_eq!(
+            arms[0].body.result.as_deref().unwrap().kind,
+            ExprKind::Number(1)
+        );
+        assert_eq!(arms[1].pattern, Pattern::Wildcard(Span::new(56, 57)));
+        assert_eq!(
+            arms[1].body.result.as_deref().unwrap().kind,
+            ExprKind::Number(2)
+        );
+    }
+
+    #[test]
+    fn should_wrap_else_if_in_block() {
+        let module = parse(
+            "func f() { if 0 { 1 } else if 1 { 2 } else { 3 } }",
+            Source::Input,
+        )
+        .unwrap();
+        let result = module.functions[0].body.result.as_deref().unwrap();
+
+        let ExprKind::If { else_block, .. } = &result.kind else {
+            panic!("expected if expression");
+        };
+
+        assert!(else_block.statements.is_empty());
+        assert!(matches!(
+            else_block.result.as_deref().unwrap().kind,
+            ExprKind::If { .. }
+        ));
+    }
+
+    #[test]
+    fn should_tag_spans_with_source() {
+        let error = parse("func f() {", Source::Prelude).unwrap_err();
+
+        assert!(matches!(
+            error,
+            CompileError::Syntax {
+                span: Span {
+                    source: Source::Prelude,
+                    start: 10,
+                    end: 10
+                },
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,166 @@
+use std::iter::repeat_n;
+
+use crate::wasm::{Instruction, ValueType};
+
+use super::Function;
+use super::symbols::Symbols;
+use super::types::Type;
+
+pub const MARK_GLOBALS: &str = "__gcMarkGlobals";
+
+// Generated rather than defined in the prelude, since only the compiler knows
+// which globals may hold pointers.
+pub fn mark_globals(symbols: &Symbols) -> Option<Vec<Instruction>> {
+    let mark = symbols.function("__gcMark")?;
+    let mut instructions = vec![];
+
+    for global in symbols.globals() {
+        if let (true, ValueType::I32, Some(index)) =
+            (global.mutable, global.r#type.value_type(), global.index)
+        {
+            instructions.push(Instruction::GlobalGet(index));
+            instructions.push(Instruction::Call(mark));
+        }
+    }
+
+    instructions.push(Instruction::End);
+
+    Some(instructions)
+}
+
+// A collection can only start in a call that reaches `__gcCollect`, or in an
+// import, which may call back into an export. Only functions making such a
+// call can lose a pointer they hold, so only they need a frame. Calls are read
+// from the functions collected without frames, which include the calls to the
+// runtime that allocations and other builtins make.
+pub fn framed(functions: &[Function], imports: usize, symbols: &Symbols) -> Vec<bool> {
+    let calls: Vec<Vec<usize>> = functions
+        .iter()
+        .map(|function| {
+            function
+                .instructions
+                .iter()
+                .filter_map(|instruction| match instruction {
+                    Instruction::Call(index) => Some(*index),
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect();
+
+    let collect = symbols.function("__gcCollect");
+    let mut collects: Vec<_> = (0..imports + functions.len())
+        .map(|index| index < imports || Some(index) == collect)
+        .collect();
+
+    let reaches = |collects: &[bool], function: usize| {
+        calls[function]
+            .iter()
+            .any(|&index| collects.get(index) == Some(&true))
+    };
+
+    while let Some(function) = (0..functions.len())
+        .find(|&function| !collects[imports + function] && reaches(&collects, function))
+    {
+        collects[imports + function] = true;
+    }
+
+    (0..functions.len())
+        .map(|function| reaches(&collects, function))
+        .collect()
+}
+
+// With the garbage collector enabled, functions that may start a collection
+// keep a copy of every `i32` that might be a pointer in a frame on the shadow
+// stack, which the collector scans for roots. Locals are copied before each
+// call, the only place a collection can start, and call results are kept in a
+// slot of their own until the function returns, since they may still be on
+// the operand stack.
+pub struct ShadowFrame {
+    // Two extra locals: the address of the frame and a scratch slot for
+    // copying call results.
+    frame: usize,
+    scratch: usize,
+    roots: Vec<usize>,
+    results: usize,
+    enter: usize,
+    top: usize,
+}
+
+impl ShadowFrame {
+    pub fn new(
+        parameters: &[Type],
+        locals: &[(usize, ValueType)],
+        symbols: &Symbols,
+    ) -> Option<Self> {
+        let types: Vec<_> = parameters
+            .iter()
+            .map(|parameter| parameter.value_type())
+            .chain(
+                locals
+                    .iter()
+                    .flat_map(|&(count, r#type)| repeat_n(r#type, count)),
+            )
+            .collect();
+
+        Some(Self {
+            frame: types.len(),
+            scratch: types.len() + 1,
+            roots: (0..types.len())
+                .filter(|&index| types[index] == ValueType::I32)
+                .collect(),
+            results: 0,
+            enter: symbols.function("__gcEnter")?,
+            top: symbols.global_index("__gcStackTop")?,
+        })
+    }
+
+    pub fn locals(&self) -> (usize, ValueType) {
+        (2, ValueType::I32)
+    }
+
+    fn store(&self, slot: usize, local: usize) -> [Instruction; 3] {
+        [
+            Instruction::LocalGetI32(self.frame),
+            Instruction::LocalGetI32(local),
+            Instruction::StoreI32(2, slot * 4),
+        ]
+    }
+
+    // Runs before each call, after its arguments have been pushed.
+    pub fn spill(&self) -> Vec<Instruction> {
+        self.roots
+            .iter()
+            .enumerate()
+            .flat_map(|(slot, &local)| self.store(slot, local))
+            .collect()
+    }
+
+    // Runs after each call returning an `i32`, leaving the result in place.
+    pub fn keep(&mut self) -> Vec<Instruction> {
+        let slot = self.roots.len() + self.results;
+        self.results += 1;
+
+        [Instruction::LocalTeeI32(self.scratch)]
+            .into_iter()
+            .chain(self.store(slot, self.scratch))
+            .collect()
+    }
+
+    // Only known once the whole body has been collected, because every call
+    // site needs a slot.
+    pub fn prologue(&self) -> Vec<Instruction> {
+        vec![
+            Instruction::ConstI32(((self.roots.len() + self.results) * 4) as i32),
+            Instruction::Call(self.enter),
+            Instruction::LocalSetI32(self.frame),
+        ]
+    }
+
+    pub fn epilogue(&self) -> Vec<Instruction> {
+        vec![
+            Instruction::LocalGetI32(self.frame),
+            Instruction::GlobalSet(self.top),
+        ]
+    }
+}
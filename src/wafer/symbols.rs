@@ -1,173 +1,956 @@
-use std::collections::HashMap;
-
-use itertools::Itertools;
-use pest::iterators::Pair;
+use std::collections::{HashMap, HashSet};
 
 use crate::wasm::ValueType;
 
-use super::Rule;
+use super::ast::{
+    self, Block, Enum, Expr, ExprKind, Function, Global, Identifier, Iterable, Module, Parameter,
+    Stmt, StmtKind, Visitor,
+};
+use super::constants::{self, Constants, Value};
+use super::data::{self, Data};
+use super::shadow::MARK_GLOBALS;
+use super::types::{self, Element, Environment, Type, TypeName, TypeNames};
+use super::{CompileError, Span};
+
+struct FunctionSymbols {
+    name: String,
+    result: Option<Type>,
+    parameters: Vec<Type>,
+    // The types of the local slots that follow the parameters.
+    locals: Vec<Type>,
+    // The slot each variable use or declaration refers to, keyed by the
+    // position of its identifier.
+    resolved: HashMap<usize, usize>,
+    hidden: HashMap<String, usize>,
+}
 
-#[derive(PartialEq, Eq, Hash, Clone)]
-pub enum SymbolKind {
-    Parameter,
-    LocalVariable,
+impl FunctionSymbols {
+    fn slot_type(&self, slot: usize) -> Type {
+        slot_type(&self.parameters, &self.locals, slot)
+    }
 }
 
-pub struct Symbol {
-    index: usize,
-    r#type: ValueType,
-    kind: SymbolKind,
+// Module-level variables live in WebAssembly globals. Constants are folded into
+// their uses, and only get a global when they are exported.
+pub struct GlobalSymbol {
+    pub name: String,
+    pub public: bool,
+    pub mutable: bool,
+    pub r#type: Type,
+    pub value: Value,
+    pub index: Option<usize>,
 }
 
-pub struct Symbols(Vec<(String, HashMap<String, Symbol>)>);
+pub struct FieldSymbol {
+    pub name: String,
+    pub r#type: Type,
+    pub offset: usize,
+}
 
-fn param_symbols(pair: Pair<Rule>) -> impl Iterator<Item = (String, SymbolKind)> {
-    pair.into_inner()
-        .map(|p| (p.as_str().to_string(), SymbolKind::Parameter))
+// Fields are laid out in declaration order, each aligned to its own size.
+pub struct StructSymbol {
+    pub name: TypeName,
+    pub fields: Vec<FieldSymbol>,
+    pub size: usize,
 }
 
-fn local_symbols(pair: Pair<Rule>) -> impl Iterator<Item = (String, SymbolKind)> {
-    pair.into_inner()
-        .flatten()
-        .filter_map(|pair| match pair.as_rule() {
-            Rule::let_statement => {
-                let pair = pair.into_inner().next().unwrap();
-                Some((pair.as_str().to_string(), SymbolKind::LocalVariable))
-            }
-            Rule::array_assignment_expression => {
-                Some(("$temp".to_string(), SymbolKind::LocalVariable))
+pub struct VariantSymbol {
+    pub name: String,
+    pub discriminant: i32,
+}
+
+// Variants are numbered from zero in declaration order, unless they are given
+// a discriminant, which the following variants count up from.
+pub struct EnumSymbol {
+    pub name: TypeName,
+    pub variants: Vec<VariantSymbol>,
+}
+
+// Structs and enums share a namespace, so that type annotations can refer to
+// either.
+#[derive(Default)]
+struct Definitions {
+    names: TypeNames,
+    structs: Vec<StructSymbol>,
+    enums: Vec<EnumSymbol>,
+}
+
+pub enum Variable {
+    Local(Type, usize),
+    Global(Type, usize),
+    Constant(Value),
+}
+
+pub struct Symbols {
+    functions: Vec<FunctionSymbols>,
+    definitions: Definitions,
+    globals: Vec<GlobalSymbol>,
+    data: Data,
+}
+
+fn slot_type(parameters: &[Type], locals: &[Type], slot: usize) -> Type {
+    match slot.checked_sub(parameters.len()) {
+        Some(local) => locals[local],
+        None => parameters[slot],
+    }
+}
+
+fn find_struct(structs: &[StructSymbol], r#type: Type) -> Option<&StructSymbol> {
+    match r#type {
+        Type::Struct(name) => structs.iter().find(|r#struct| r#struct.name == name),
+        _ => None,
+    }
+}
+
+fn find_enum(enums: &[EnumSymbol], r#type: Type) -> Option<&EnumSymbol> {
+    match r#type {
+        Type::Enum(name) => enums.iter().find(|r#enum| r#enum.name == name),
+        _ => None,
+    }
+}
+
+fn find_field<'a>(
+    structs: &'a [StructSymbol],
+    r#type: Type,
+    field: &str,
+) -> Option<&'a FieldSymbol> {
+    find_struct(structs, r#type)?
+        .fields
+        .iter()
+        .find(|symbol| symbol.name == field)
+}
+
+fn resolve_type(
+    annotation: Option<&Identifier>,
+    definitions: &Definitions,
+    errors: &mut Vec<CompileError>,
+) -> Option<Type> {
+    let annotation = annotation?;
+    let r#type = Type::from_name(&annotation.name).or_else(|| {
+        let name = definitions.names.find(&annotation.name)?;
+
+        find_struct(&definitions.structs, Type::Struct(name))
+            .map(|_| Type::Struct(name))
+            .or_else(|| find_enum(&definitions.enums, Type::Enum(name)).map(|_| Type::Enum(name)))
+    });
+
+    if r#type.is_none() {
+        errors.push(CompileError::unknown_type(
+            &annotation.name,
+            annotation.span,
+            &definitions.names,
+        ));
+    }
+
+    r#type
+}
+
+fn param_types(
+    parameters: &[Parameter],
+    definitions: &Definitions,
+    errors: &mut Vec<CompileError>,
+) -> Vec<Type> {
+    let mut seen = HashSet::new();
+
+    parameters
+        .iter()
+        .map(|parameter| {
+            if !seen.insert(&parameter.name.name) {
+                errors.push(CompileError::DuplicateDefinition {
+                    name: parameter.name.name.clone(),
+                    span: parameter.name.span,
+                });
             }
-            _ => None,
+
+            resolve_type(parameter.r#type.as_ref(), definitions, errors).unwrap_or(Type::I32)
         })
-        .unique()
+        .collect()
 }
 
-fn function_symbols(pair: Pair<'_, Rule>) -> (String, HashMap<String, Symbol>) {
-    let mut pairs = pair.into_inner();
-    let name = pairs.next().unwrap();
-    let params = pairs.next().unwrap();
-    let body = pairs.next().unwrap();
+// Walks a function body with a stack of block scopes, resolving every
+// variable to a slot. Slots are released when their scope ends and reused by
+// later declarations of the same type, so disjoint blocks share locals.
+struct LocalCollector<'a> {
+    results: &'a HashMap<String, Option<Type>>,
+    definitions: &'a Definitions,
+    globals: &'a [GlobalSymbol],
+    parameters: Vec<Type>,
+    locals: Vec<Type>,
+    scopes: Vec<Vec<(String, usize)>>,
+    free: Vec<usize>,
+    resolved: HashMap<usize, usize>,
+    hidden: HashMap<String, usize>,
+    declared: HashSet<String>,
+    undeclared: Vec<Identifier>,
+    errors: &'a mut Vec<CompileError>,
+}
 
-    let symbols = param_symbols(params)
-        .chain(local_symbols(body))
-        .enumerate()
-        .map(|(index, (name, kind))| {
-            (
-                name,
-                Symbol {
-                    index,
-                    r#type: ValueType::I32,
-                    kind,
-                },
-            )
-        })
-        .collect();
+impl<'a> LocalCollector<'a> {
+    fn new(
+        function: &Function,
+        parameters: Vec<Type>,
+        results: &'a HashMap<String, Option<Type>>,
+        definitions: &'a Definitions,
+        globals: &'a [GlobalSymbol],
+        errors: &'a mut Vec<CompileError>,
+    ) -> Self {
+        let names: Vec<_> = function
+            .parameters
+            .iter()
+            .map(|parameter| parameter.name.name.clone())
+            .collect();
+
+        Self {
+            results,
+            definitions,
+            globals,
+            parameters,
+            locals: vec![],
+            scopes: vec![names.iter().cloned().zip(0..).collect()],
+            free: vec![],
+            resolved: HashMap::new(),
+            hidden: HashMap::new(),
+            declared: names.into_iter().collect(),
+            undeclared: vec![],
+            errors,
+        }
+    }
+
+    fn allocate(&mut self, r#type: Type) -> usize {
+        let reusable = self
+            .free
+            .iter()
+            .position(|&slot| slot_type(&self.parameters, &self.locals, slot) == r#type);
 
-    (name.as_str().to_string(), symbols)
+        match reusable {
+            Some(position) => self.free.swap_remove(position),
+            None => {
+                self.locals.push(r#type);
+                self.parameters.len() + self.locals.len() - 1
+            }
+        }
+    }
+
+    fn declare(&mut self, identifier: &Identifier, r#type: Type) {
+        let slot = self.allocate(r#type);
+
+        self.scopes
+            .last_mut()
+            .expect("declarations are always inside a scope")
+            .push((identifier.name.clone(), slot));
+        self.resolved.insert(identifier.span.start, slot);
+        self.declared.insert(identifier.name.clone());
+    }
+
+    // Hidden locals live as long as the innermost scope, except the
+    // temporaries, which are shared by the whole function.
+    fn declare_hidden(&mut self, name: String, r#type: Type) {
+        let slot = self.allocate(r#type);
+        let scope = if name.starts_with("$temp") {
+            self.scopes.first_mut()
+        } else {
+            self.scopes.last_mut()
+        };
+
+        scope
+            .expect("declarations are always inside a scope")
+            .push((name.clone(), slot));
+        self.hidden.insert(name, slot);
+    }
+
+    fn declare_temp(&mut self, r#type: Type) {
+        let name = temp_local(r#type);
+
+        if !self.hidden.contains_key(&name) {
+            self.declare_hidden(name, Type::from(r#type.value_type()));
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(declared, _)| declared == name)
+            .map(|(_, slot)| *slot)
+    }
+
+    fn resolve(&mut self, identifier: &Identifier) {
+        if let Some(slot) = self.lookup(&identifier.name) {
+            self.resolved.insert(identifier.span.start, slot);
+        } else if find_global(self.globals, &identifier.name).is_some() {
+            // Globals are resolved by name during code generation.
+        } else if self.declared.contains(&identifier.name) {
+            self.errors.push(CompileError::OutOfScope {
+                name: identifier.name.clone(),
+                span: identifier.span,
+            });
+        } else {
+            self.undeclared.push(identifier.clone());
+        }
+    }
+
+    fn finish(self, function: &mut FunctionSymbols) {
+        for identifier in self.undeclared {
+            let name = identifier.name.clone();
+            let span = identifier.span;
+
+            self.errors.push(if self.declared.contains(&name) {
+                CompileError::UseBeforeDeclaration { name, span }
+            } else {
+                CompileError::UnknownVariable { name, span }
+            });
+        }
+
+        function.locals = self.locals;
+        function.resolved = self.resolved;
+        function.hidden = self.hidden;
+    }
 }
 
-impl From<Pair<'_, Rule>> for Symbols {
-    fn from(pair: Pair<Rule>) -> Self {
-        let mut imports = vec![];
-        let mut functions = vec![];
+impl Environment for LocalCollector<'_> {
+    fn variable(&self, identifier: &Identifier) -> Option<Type> {
+        self.lookup(&identifier.name)
+            .map(|slot| slot_type(&self.parameters, &self.locals, slot))
+            .or_else(|| find_global(self.globals, &identifier.name).map(|global| global.r#type))
+    }
+
+    fn result(&self, function: &str) -> Option<Type> {
+        self.results.get(function).copied().flatten()
+    }
+
+    fn field(&self, r#type: Type, field: &str) -> Option<Type> {
+        find_field(&self.definitions.structs, r#type, field).map(|field| field.r#type)
+    }
+
+    fn type_names(&self) -> &TypeNames {
+        &self.definitions.names
+    }
+}
+
+impl Visitor for LocalCollector<'_> {
+    fn visit_block(&mut self, block: &Block) {
+        self.scopes.push(vec![]);
+        ast::walk_block(self, block);
 
-        for pair in pair.into_inner() {
-            match pair.as_rule() {
-                Rule::function => {
-                    functions.push(function_symbols(pair));
+        let scope = self.scopes.pop().expect("scopes are balanced");
+        self.free.extend(scope.into_iter().map(|(_, slot)| slot));
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            // The initializer is resolved before the name is declared, so
+            // `let x = x + 1;` refers to an outer `x`.
+            StmtKind::Let {
+                name,
+                r#type,
+                value,
+            } => {
+                self.visit_expr(value);
+
+                let r#type = resolve_type(r#type.as_ref(), self.definitions, self.errors)
+                    .or_else(|| types::infer(value, self))
+                    .unwrap_or_else(|| types::default_type(value));
+
+                self.declare(name, r#type);
+            }
+            StmtKind::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                self.scopes.push(vec![]);
+
+                match iterable {
+                    Iterable::Range { start, end } => {
+                        self.visit_expr(start);
+                        self.visit_expr(end);
+
+                        let r#type = types::infer(start, self)
+                            .or_else(|| types::infer(end, self))
+                            .unwrap_or(Type::I32);
+
+                        self.declare(variable, r#type);
+                        self.declare_hidden(hidden_name("end", stmt.span), r#type);
+                    }
+                    Iterable::Array(array) => {
+                        self.visit_expr(array);
+
+                        // The array's local keeps its type, which decides the
+                        // width of the elements.
+                        let element = types::infer(array, self).map_or(Element::I32, Type::element);
+
+                        self.declare_hidden(hidden_name("array", stmt.span), Type::Array(element));
+                        self.declare_hidden(hidden_name("index", stmt.span), Type::I32);
+                        self.declare(variable, Type::I32);
+                    }
                 }
-                Rule::public_function => {
-                    functions.push(function_symbols(pair.into_inner().next().unwrap()));
+
+                self.visit_block(body);
+
+                let scope = self.scopes.pop().expect("scopes are balanced");
+                self.free.extend(scope.into_iter().map(|(_, slot)| slot));
+            }
+            _ => ast::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Variable(identifier) if identifier.name != "__heap_base" => {
+                self.resolve(identifier);
+            }
+            ExprKind::Assign { target, .. } => self.resolve(target),
+            // The memory intrinsics aren't variables, so only their index and
+            // value are visited.
+            ExprKind::Index { array, index } if Element::from_memory(array).is_some() => {
+                self.visit_expr(index);
+                return;
+            }
+            ExprKind::IndexAssign {
+                array,
+                index,
+                value,
+            } if Element::from_memory(array).is_some() => {
+                self.declare_temp(Type::I32);
+                self.visit_expr(index);
+                self.visit_expr(value);
+                return;
+            }
+            ExprKind::FieldAssign { .. } => {
+                if let Some(r#type) = types::infer(expr, self) {
+                    self.declare_temp(r#type);
                 }
-                Rule::external_function => {
-                    let mut pairs = pair.into_inner();
-                    let name = pairs.next().unwrap();
-                    let params = pairs.next().unwrap();
-
-                    let symbols = param_symbols(params)
-                        .enumerate()
-                        .map(|(index, (name, kind))| {
-                            (
-                                name,
-                                Symbol {
-                                    index,
-                                    r#type: ValueType::I32,
-                                    kind,
-                                },
-                            )
-                        })
-                        .collect();
-
-                    imports.push((name.as_str().to_string(), symbols));
+            }
+            // The new struct is kept in a local while its fields are stored.
+            ExprKind::Struct { .. } => {
+                self.declare_hidden(hidden_name("struct", expr.span), Type::I32)
+            }
+            ExprKind::Array(elements) if !data::is_constant(elements) => {
+                self.declare_hidden(hidden_name("elements", expr.span), Type::I32)
+            }
+            // The matched value is kept in a local while it is compared.
+            ExprKind::Match { .. } => {
+                self.declare_hidden(hidden_name("match", expr.span), Type::I32)
+            }
+            _ => (),
+        }
+
+        ast::walk_expr(self, expr);
+    }
+}
+
+fn find_global<'a>(globals: &'a [GlobalSymbol], name: &str) -> Option<&'a GlobalSymbol> {
+    globals.iter().find(|global| global.name == name)
+}
+
+// Initializers are evaluated in order, so they can only refer to constants
+// declared above them.
+struct GlobalScope<'a>(&'a [GlobalSymbol], &'a Data, &'a TypeNames);
+
+impl Environment for GlobalScope<'_> {
+    fn variable(&self, identifier: &Identifier) -> Option<Type> {
+        find_global(self.0, &identifier.name).map(|global| global.r#type)
+    }
+
+    fn result(&self, _: &str) -> Option<Type> {
+        None
+    }
+
+    fn field(&self, _: Type, _: &str) -> Option<Type> {
+        None
+    }
+
+    fn type_names(&self) -> &TypeNames {
+        self.2
+    }
+}
+
+impl Constants for GlobalScope<'_> {
+    fn constant(&self, identifier: &Identifier) -> Option<Value> {
+        find_global(self.0, &identifier.name)
+            .filter(|global| !global.mutable)
+            .map(|global| global.value)
+    }
+
+    fn string(&self, value: &str) -> Option<i32> {
+        self.1.string(value)
+    }
+}
+
+fn global_symbols(
+    globals: &[Global],
+    definitions: &Definitions,
+    data: &Data,
+    errors: &mut Vec<CompileError>,
+) -> Vec<GlobalSymbol> {
+    let mut symbols: Vec<GlobalSymbol> = vec![];
+    let mut next_index = 0;
+
+    for global in globals {
+        if find_global(&symbols, &global.name.name).is_some() {
+            errors.push(CompileError::DuplicateDefinition {
+                name: global.name.name.clone(),
+                span: global.name.span,
+            });
+
+            continue;
+        }
+
+        let scope = GlobalScope(&symbols, data, &definitions.names);
+        let r#type = resolve_type(global.r#type.as_ref(), definitions, errors)
+            .or_else(|| types::infer(&global.value, &scope))
+            .unwrap_or_else(|| types::default_type(&global.value));
+        let value = constants::evaluate(&global.value, r#type, &scope).unwrap_or_else(|error| {
+            errors.push(error);
+            Value::zero(r#type)
+        });
+
+        let mutable = !global.constant;
+        let index = (mutable || global.public).then(|| {
+            next_index += 1;
+            next_index - 1
+        });
+
+        symbols.push(GlobalSymbol {
+            name: global.name.name.clone(),
+            public: global.public,
+            mutable,
+            r#type,
+            value,
+            index,
+        });
+    }
+
+    symbols
+}
+
+// Names for the hidden locals of `for` loops, struct and array literals and
+// `match` expressions, made unique by the construct's position and
+// unspellable in source by the `$`.
+pub fn hidden_name(name: &str, span: Span) -> String {
+    format!("${name}{}", span.start)
+}
+
+// Assignments through memory keep the stored value in a temporary, one for
+// each value type.
+pub fn temp_local(r#type: Type) -> String {
+    match r#type.value_type() {
+        ValueType::I32 => "$temp".to_string(),
+        value_type => format!("$temp{}", Type::from(value_type)),
+    }
+}
+
+fn is_type_defined(
+    definitions: &Definitions,
+    name: &Identifier,
+    errors: &mut Vec<CompileError>,
+) -> bool {
+    let defined =
+        Type::from_name(&name.name).is_some() || definitions.names.find(&name.name).is_some();
+
+    if defined {
+        errors.push(CompileError::DuplicateDefinition {
+            name: name.name.clone(),
+            span: name.span,
+        });
+    }
+
+    defined
+}
+
+fn enum_symbol(name: TypeName, r#enum: &Enum, errors: &mut Vec<CompileError>) -> EnumSymbol {
+    let mut variants: Vec<VariantSymbol> = vec![];
+    let mut next = Some(0);
+
+    for variant in &r#enum.variants {
+        let discriminant = match &variant.discriminant {
+            Some(value) => {
+                let scope = GlobalScope(&[], &Data::default(), &TypeNames::new());
+
+                match constants::evaluate(value, Type::I32, &scope) {
+                    Ok(Value::I32(discriminant)) => Some(discriminant),
+                    Ok(_) => None,
+                    Err(error) => {
+                        errors.push(error);
+                        None
+                    }
+                }
+            }
+            None => {
+                if next.is_none() {
+                    errors.push(CompileError::NumberOutOfRange {
+                        value: (i128::from(i32::MAX) + 1).to_string(),
+                        r#type: Type::I32,
+                        span: variant.name.span,
+                    });
                 }
-                _ => (),
+
+                next
             }
+        };
+
+        next = discriminant.and_then(|discriminant| discriminant.checked_add(1));
+
+        if variants
+            .iter()
+            .any(|symbol| symbol.name == variant.name.name)
+        {
+            errors.push(CompileError::DuplicateDefinition {
+                name: variant.name.name.clone(),
+                span: variant.name.span,
+            });
+
+            continue;
+        }
+
+        let Some(discriminant) = discriminant else {
+            continue;
+        };
+
+        if variants
+            .iter()
+            .any(|symbol| symbol.discriminant == discriminant)
+        {
+            errors.push(CompileError::DuplicateDiscriminant {
+                value: discriminant,
+                span: variant.name.span,
+            });
+        }
+
+        variants.push(VariantSymbol {
+            name: variant.name.name.clone(),
+            discriminant,
+        });
+    }
+
+    EnumSymbol { name, variants }
+}
+
+// Every struct and enum name is known before any field is resolved, so fields
+// can refer to other types regardless of order, and to their own struct.
+fn definitions(module: &Module, errors: &mut Vec<CompileError>) -> Definitions {
+    let mut definitions = Definitions::default();
+    let mut structs = vec![];
+
+    for r#enum in &module.enums {
+        if !is_type_defined(&definitions, &r#enum.name, errors) {
+            let name = definitions.names.intern(&r#enum.name.name);
+            let symbol = enum_symbol(name, r#enum, errors);
+            definitions.enums.push(symbol);
+        }
+    }
+
+    for r#struct in &module.structs {
+        if !is_type_defined(&definitions, &r#struct.name, errors) {
+            definitions.structs.push(StructSymbol {
+                name: definitions.names.intern(&r#struct.name.name),
+                fields: vec![],
+                size: 0,
+            });
+            structs.push(r#struct);
+        }
+    }
+
+    for (index, r#struct) in structs.into_iter().enumerate() {
+        let types = param_types(&r#struct.fields, &definitions, errors);
+        let mut fields = vec![];
+        let mut size: usize = 0;
+
+        for (field, r#type) in r#struct.fields.iter().zip(types) {
+            let width = match r#type.value_type() {
+                ValueType::I32 | ValueType::F32 => 4,
+                ValueType::I64 | ValueType::F64 => 8,
+            };
+            let offset = size.next_multiple_of(width);
+
+            fields.push(FieldSymbol {
+                name: field.name.name.clone(),
+                r#type,
+                offset,
+            });
+            size = offset + width;
         }
 
-        let symbols = imports.into_iter().chain(functions).collect();
-        Self(symbols)
+        definitions.structs[index].fields = fields;
+        definitions.structs[index].size = size;
     }
+
+    definitions
+}
+
+fn ends_in_void_call(function: &Function, symbols: &[FunctionSymbols]) -> bool {
+    matches!(
+        function.body.result.as_deref(),
+        Some(Expr { kind: ExprKind::Call { function, .. }, .. })
+            if symbols
+                .iter()
+                .any(|symbol| symbol.name == function.name && symbol.result.is_none())
+    )
+}
+
+fn is_duplicate(
+    defined: &[FunctionSymbols],
+    name: &Identifier,
+    errors: &mut Vec<CompileError>,
+) -> bool {
+    let duplicate = defined.iter().any(|function| function.name == name.name);
+
+    if duplicate {
+        errors.push(CompileError::DuplicateDefinition {
+            name: name.name.clone(),
+            span: name.span,
+        });
+    }
+
+    duplicate
 }
 
 impl Symbols {
-    pub fn local(&self, function_name: &str, local_name: &str) -> (ValueType, usize) {
-        let symbol = self
-            .symbols_for_function(function_name)
-            .get(local_name)
-            .expect("couldn't find symbol");
+    pub fn new(module: &Module, errors: &mut Vec<CompileError>) -> Self {
+        let definitions = definitions(module, errors);
+        let data = Data::from(module);
+        let globals = global_symbols(&module.globals, &definitions, &data, errors);
+        let mut symbols = vec![];
+
+        for import in &module.imports {
+            if !is_duplicate(&symbols, &import.name, errors) {
+                symbols.push(FunctionSymbols {
+                    name: import.name.name.clone(),
+                    result: resolve_type(import.returns.as_ref(), &definitions, errors),
+                    parameters: param_types(&import.parameters, &definitions, errors),
+                    locals: vec![],
+                    resolved: HashMap::new(),
+                    hidden: HashMap::new(),
+                });
+            }
+        }
 
-        (symbol.r#type, symbol.index)
+        let num_imports = symbols.len();
+        let mut bodies: Vec<&Function> = vec![];
+
+        for function in &module.functions {
+            if !is_duplicate(&symbols, &function.name, errors) {
+                symbols.push(FunctionSymbols {
+                    name: function.name.name.clone(),
+                    result: resolve_type(function.returns.as_ref(), &definitions, errors)
+                        .or(function.body.result.as_ref().map(|_| Type::I32)),
+                    parameters: param_types(&function.parameters, &definitions, errors),
+                    locals: vec![],
+                    resolved: HashMap::new(),
+                    hidden: HashMap::new(),
+                });
+                bodies.push(function);
+            }
+        }
+
+        // Bodies ending in a call to a void function are void themselves, which
+        // for calls to other such bodies is only known once theirs is.
+        while let Some(index) = (num_imports..symbols.len()).find(|&index| {
+            let function = bodies[index - num_imports];
+
+            symbols[index].result.is_some()
+                && function.returns.is_none()
+                && ends_in_void_call(function, &symbols)
+        }) {
+            symbols[index].result = None;
+        }
+
+        // The collector's runtime calls a function marking the globals, which
+        // is generated once they are all known.
+        if symbols.iter().any(|function| function.name == "__gcMark") {
+            symbols.push(FunctionSymbols {
+                name: MARK_GLOBALS.to_string(),
+                result: None,
+                parameters: vec![],
+                locals: vec![],
+                resolved: HashMap::new(),
+                hidden: HashMap::new(),
+            });
+        }
+
+        // Local types may be inferred from calls, so every signature has to be
+        // known before any function body is walked.
+        let results = symbols
+            .iter()
+            .map(|function| (function.name.clone(), function.result))
+            .collect();
+
+        for (function, body) in symbols[num_imports..].iter_mut().zip(bodies) {
+            let mut collector = LocalCollector::new(
+                body,
+                function.parameters.clone(),
+                &results,
+                &definitions,
+                &globals,
+                errors,
+            );
+
+            collector.visit_block(&body.body);
+            collector.finish(function);
+        }
+
+        Self {
+            functions: symbols,
+            definitions,
+            globals,
+            data,
+        }
+    }
+
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Data {
+        self.data
+    }
+
+    pub fn variable(&self, function_name: &str, identifier: &Identifier) -> Option<Variable> {
+        if let Some((r#type, index)) = self.local(function_name, identifier) {
+            return Some(Variable::Local(r#type, index));
+        }
+
+        let global = find_global(&self.globals, &identifier.name)?;
+
+        Some(match (global.mutable, global.index) {
+            (true, Some(index)) => Variable::Global(global.r#type, index),
+            _ => Variable::Constant(global.value),
+        })
+    }
+
+    // Constants keep their declared type, which strings and enums don't share
+    // with their value.
+    pub fn variable_type(&self, function_name: &str, identifier: &Identifier) -> Option<Type> {
+        match self.local(function_name, identifier) {
+            Some((r#type, _)) => Some(r#type),
+            None => find_global(&self.globals, &identifier.name).map(|global| global.r#type),
+        }
+    }
+
+    pub fn r#struct(&self, r#type: Type) -> Option<&StructSymbol> {
+        find_struct(&self.definitions.structs, r#type)
+    }
+
+    pub fn r#enum(&self, r#type: Type) -> Option<&EnumSymbol> {
+        find_enum(&self.definitions.enums, r#type)
+    }
+
+    pub fn variant(&self, r#type: Type, variant: &str) -> Option<i32> {
+        self.r#enum(r#type)?
+            .variants
+            .iter()
+            .find(|symbol| symbol.name == variant)
+            .map(|symbol| symbol.discriminant)
+    }
+
+    // The value a `match` pattern stands for: a number or an enum variant.
+    pub fn pattern(&self, pattern: &Expr) -> Option<i32> {
+        match &pattern.kind {
+            ExprKind::Number(number) => i32::try_from(*number).ok(),
+            ExprKind::Variant { r#enum, variant } => {
+                self.variant(Type::Enum(self.type_name(&r#enum.name)?), &variant.name)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn type_names(&self) -> &TypeNames {
+        &self.definitions.names
+    }
+
+    pub fn type_name(&self, name: &str) -> Option<TypeName> {
+        self.definitions.names.find(name)
+    }
+
+    pub fn field(&self, r#type: Type, field: &str) -> Option<&FieldSymbol> {
+        find_field(&self.definitions.structs, r#type, field)
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = &GlobalSymbol> {
+        self.globals.iter().filter(|global| global.index.is_some())
+    }
+
+    pub fn global_index(&self, name: &str) -> Option<usize> {
+        find_global(&self.globals, name).and_then(|global| global.index)
+    }
+
+    pub fn local(&self, function_name: &str, identifier: &Identifier) -> Option<(Type, usize)> {
+        let function = self.function_symbols(function_name);
+
+        function
+            .resolved
+            .get(&identifier.span.start)
+            .map(|&slot| (function.slot_type(slot), slot))
+    }
+
+    pub fn hidden_local(&self, function_name: &str, name: &str) -> Option<(Type, usize)> {
+        let function = self.function_symbols(function_name);
+
+        function
+            .hidden
+            .get(name)
+            .map(|&slot| (function.slot_type(slot), slot))
     }
 
     pub fn locals(&self, function_name: &str) -> Vec<(usize, ValueType)> {
-        let mut locals: HashMap<ValueType, usize> = HashMap::new();
+        let mut locals: Vec<(usize, ValueType)> = vec![];
 
-        for symbol in self
-            .symbols_for_function(function_name)
-            .values()
-            .filter(|symbol| symbol.kind == SymbolKind::LocalVariable)
-        {
-            *locals.entry(symbol.r#type).or_insert(0) += 1;
+        for local in &self.function_symbols(function_name).locals {
+            let value_type = local.value_type();
+
+            match locals.last_mut() {
+                Some((count, r#type)) if *r#type == value_type => *count += 1,
+                _ => locals.push((1, value_type)),
+            }
         }
 
         locals
-            .into_iter()
-            .map(|(r#type, count)| (count, r#type))
-            .collect()
     }
 
-    pub fn parameters(&self, function_name: &str) -> Vec<ValueType> {
-        self.symbols_for_function(function_name)
-            .values()
-            .filter(|symbol| symbol.kind == SymbolKind::Parameter)
-            .map(|symbol| symbol.r#type)
-            .collect()
+    pub fn parameters(&self, function_name: &str) -> Vec<Type> {
+        self.function_symbols(function_name).parameters.clone()
     }
 
-    pub fn function(&self, function_name: &str) -> usize {
-        self.0
+    pub fn result(&self, function_name: &str) -> Option<Type> {
+        self.functions
             .iter()
-            .position(|(name, _)| function_name == name)
-            .expect("couldn't find function")
+            .find(|function| function.name == function_name)
+            .and_then(|function| function.result)
     }
 
-    fn symbols_for_function(&self, function_name: &str) -> &HashMap<String, Symbol> {
-        self.0
+    pub fn is_void(&self, function_name: &str) -> bool {
+        self.functions
             .iter()
-            .find(|(name, _)| name == function_name)
-            .map(|(_, symbols)| symbols)
+            .any(|function| function.name == function_name && function.result.is_none())
+    }
+
+    pub fn is_void_call(&self, expr: &Expr) -> bool {
+        matches!(&expr.kind, ExprKind::Call { function, .. } if self.is_void(&function.name))
+    }
+
+    pub fn function(&self, function_name: &str) -> Option<usize> {
+        self.functions
+            .iter()
+            .position(|function| function.name == function_name)
+    }
+
+    fn function_symbols(&self, function_name: &str) -> &FunctionSymbols {
+        self.functions
+            .iter()
+            .find(|function| function.name == function_name)
             .expect("couldn't find symbols")
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use pest::Parser as PestParser;
-
-    use crate::wafer::{Parser, Rule};
+    use crate::wafer::ast::Identifier;
+    use crate::wafer::types::Type;
+    use crate::wafer::{CompileError, Source, Span, parse};
     use crate::wasm::ValueType;
 
-    use super::Symbols;
+    use super::{Symbols, Value, Variable};
 
     const WAFER: &str = r"
         extern func import(a, b);
@@ -198,24 +981,173 @@ mod tests {
         }
     ";
 
+    // The identifier at the start of the first occurrence of `marker`.
+    fn at(input: &str, marker: &str) -> Identifier {
+        let start = input.find(marker).expect("marker should be in the input");
+        let length = marker
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(marker.len());
+
+        Identifier {
+            name: marker[..length].to_string(),
+            span: Span::new(start, start + length),
+        }
+    }
+
     #[test]
     fn should_parse_symbols() {
-        let pair = Parser::parse(Rule::module, WAFER).unwrap().next().unwrap();
-        let symbols: Symbols = pair.into();
+        let module = parse(WAFER, Source::Input).unwrap();
+        let symbols = Symbols::new(&module, &mut vec![]);
 
-        assert_eq!(symbols.local("first", "a"), (ValueType::I32, 0));
-        assert_eq!(symbols.local("first", "x"), (ValueType::I32, 1));
-        assert_eq!(symbols.local("first", "y"), (ValueType::I32, 2));
-        assert_eq!(symbols.local("second", "y"), (ValueType::I32, 0));
-        assert_eq!(symbols.local("fourth", "$temp"), (ValueType::I32, 0));
+        assert_eq!(
+            symbols.local("first", &at(WAFER, "a + 42")),
+            Some((Type::I32, 0))
+        );
+        assert_eq!(
+            symbols.local("first", &at(WAFER, "x = 1")),
+            Some((Type::I32, 1))
+        );
+        assert_eq!(
+            symbols.local("first", &at(WAFER, "y = 2")),
+            Some((Type::I32, 1))
+        );
+        assert_eq!(
+            symbols.local("second", &at(WAFER, "y = 3")),
+            Some((Type::I32, 0))
+        );
+        assert_eq!(
+            symbols.hidden_local("fourth", "$temp"),
+            Some((Type::I32, 0))
+        );
+        assert_eq!(symbols.local("third", &at(WAFER, "a + 42")), None);
+    }
+
+    #[test]
+    fn should_resolve_shadowed_variables() {
+        let input = r"
+            func f(a) {
+                let b = a;
+                if a {
+                    let b: i64 = 2;
+                    let a = b + 1;
+                    a * 3;
+                }
+                b * 2
+            }
+        ";
+        let module = parse(input, Source::Input).unwrap();
+        let mut errors = vec![];
+        let symbols = Symbols::new(&module, &mut errors);
+
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            symbols.local("f", &at(input, "a * 3")),
+            Some((Type::I64, 3))
+        );
+        assert_eq!(
+            symbols.local("f", &at(input, "b + 1")),
+            Some((Type::I64, 2))
+        );
+        assert_eq!(
+            symbols.local("f", &at(input, "b * 2")),
+            Some((Type::I32, 1))
+        );
+    }
+
+    #[test]
+    fn should_reuse_slots_of_closed_scopes() {
+        let input = r"
+            func f() {
+                if 1 { let a = 1; let b: f64 = 2.0; a; }
+                if 1 { let c: f64 = 3.0; let d: i64 = 4; c; }
+                for i in 0..3 { }
+                0
+            }
+        ";
+        let module = parse(input, Source::Input).unwrap();
+        let symbols = Symbols::new(&module, &mut vec![]);
+
+        assert_eq!(
+            symbols.local("f", &at(input, "c: f64")),
+            Some((Type::F64, 1))
+        );
+        assert_eq!(
+            symbols.local("f", &at(input, "d: i64")),
+            Some((Type::I64, 2))
+        );
+        assert_eq!(symbols.local("f", &at(input, "i in")), Some((Type::I32, 0)));
+        assert_eq!(
+            symbols.locals("f"),
+            vec![
+                (1, ValueType::I32),
+                (1, ValueType::F64),
+                (1, ValueType::I64),
+                (1, ValueType::I32)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_prefer_locals_over_globals() {
+        let input = "const A = 1; let b: f32 = 2; func f() { let A = 3; A + b }";
+        let module = parse(input, Source::Input).unwrap();
+        let mut errors = vec![];
+        let symbols = Symbols::new(&module, &mut errors);
+
+        assert_eq!(errors, vec![]);
+        assert!(matches!(
+            symbols.variable("f", &at(input, "A + b")),
+            Some(Variable::Local(Type::I32, 0))
+        ));
+        assert!(matches!(
+            symbols.variable("f", &at(input, "b }")),
+            Some(Variable::Global(Type::F32, 0))
+        ));
+        assert!(matches!(
+            symbols.variable("f", &at(input, "A = 1")),
+            Some(Variable::Constant(Value::I32(1)))
+        ));
+        assert_eq!(symbols.globals().count(), 1);
+    }
+
+    #[test]
+    fn should_reject_variables_used_before_their_declaration() {
+        let input = "func f() { let a = b; let b = 1; b }";
+        let module = parse(input, Source::Input).unwrap();
+        let mut errors = vec![];
+        Symbols::new(&module, &mut errors);
+
+        assert_eq!(
+            errors,
+            vec![CompileError::UseBeforeDeclaration {
+                name: "b".to_string(),
+                span: Span::new(19, 20),
+            }]
+        );
+    }
+
+    #[test]
+    fn should_reject_variables_used_outside_their_scope() {
+        let input = "func f() { if 1 { let a = 1; } a }";
+        let module = parse(input, Source::Input).unwrap();
+        let mut errors = vec![];
+        Symbols::new(&module, &mut errors);
+
+        assert_eq!(
+            errors,
+            vec![CompileError::OutOfScope {
+                name: "a".to_string(),
+                span: Span::new(31, 32),
+            }]
+        );
     }
 
     #[test]
     fn should_get_locals() {
-        let pair = Parser::parse(Rule::module, WAFER).unwrap().next().unwrap();
-        let symbols: Symbols = pair.into();
+        let module = parse(WAFER, Source::Input).unwrap();
+        let symbols = Symbols::new(&module, &mut vec![]);
 
-        assert_eq!(symbols.locals("first"), vec![(2, ValueType::I32)]);
+        assert_eq!(symbols.locals("first"), vec![(1, ValueType::I32)]);
         assert_eq!(symbols.locals("second"), vec![(1, ValueType::I32)]);
         assert_eq!(symbols.locals("third"), vec![]);
         assert_eq!(symbols.locals("fourth"), vec![(1, ValueType::I32)]);
@@ -223,26 +1155,207 @@ mod tests {
 
     #[test]
     fn should_get_parameters() {
-        let pair = Parser::parse(Rule::module, WAFER).unwrap().next().unwrap();
-        let symbols: Symbols = pair.into();
+        let module = parse(WAFER, Source::Input).unwrap();
+        let symbols = Symbols::new(&module, &mut vec![]);
 
-        assert_eq!(
-            symbols.parameters("import"),
-            vec![ValueType::I32, ValueType::I32]
-        );
-        assert_eq!(symbols.parameters("first"), vec![ValueType::I32]);
+        assert_eq!(symbols.parameters("import"), vec![Type::I32, Type::I32]);
+        assert_eq!(symbols.parameters("first"), vec![Type::I32]);
         assert_eq!(symbols.parameters("second"), vec![]);
     }
 
     #[test]
     fn should_get_functions() {
-        let pair = Parser::parse(Rule::module, WAFER).unwrap().next().unwrap();
-        let symbols: Symbols = pair.into();
-
-        assert_eq!(symbols.function("import"), 0);
-        assert_eq!(symbols.function("first"), 1);
-        assert_eq!(symbols.function("second"), 2);
-        assert_eq!(symbols.function("third"), 3);
-        assert_eq!(symbols.function("fourth"), 4);
+        let module = parse(WAFER, Source::Input).unwrap();
+        let symbols = Symbols::new(&module, &mut vec![]);
+
+        assert_eq!(symbols.function("import"), Some(0));
+        assert_eq!(symbols.function("first"), Some(1));
+        assert_eq!(symbols.function("second"), Some(2));
+        assert_eq!(symbols.function("third"), Some(3));
+        assert_eq!(symbols.function("fourth"), Some(4));
+        assert_eq!(symbols.function("fifth"), None);
+    }
+
+    #[test]
+    fn should_resolve_annotated_and_inferred_types() {
+        let input = r"
+            extern func now(): i64;
+
+            func typed(a: i64, b): i64 {
+                let c: i64 = 1;
+                let d = now();
+                let e = 2;
+                let f = a + 1;
+                a
+            }
+        ";
+        let module = parse(input, Source::Input).unwrap();
+        let symbols = Symbols::new(&module, &mut vec![]);
+
+        assert_eq!(symbols.parameters("typed"), vec![Type::I64, Type::I32]);
+        assert_eq!(symbols.result("now"), Some(Type::I64));
+        assert_eq!(symbols.result("typed"), Some(Type::I64));
+        assert_eq!(
+            symbols.local("typed", &at(input, "c:")),
+            Some((Type::I64, 2))
+        );
+        assert_eq!(
+            symbols.local("typed", &at(input, "d =")),
+            Some((Type::I64, 3))
+        );
+        assert_eq!(
+            symbols.local("typed", &at(input, "e =")),
+            Some((Type::I32, 4))
+        );
+        assert_eq!(
+            symbols.local("typed", &at(input, "f =")),
+            Some((Type::I64, 5))
+        );
+        assert_eq!(
+            symbols.locals("typed"),
+            vec![
+                (2, ValueType::I64),
+                (1, ValueType::I32),
+                (1, ValueType::I64)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_resolve_void_functions() {
+        let input = r"
+            extern func log(a);
+            extern func now(): i64;
+
+            func run() { log(1); }
+            func value() { 1 }
+            func relay() { last() }
+            func last() { log(2) }
+        ";
+        let module = parse(input, Source::Input).unwrap();
+        let symbols = Symbols::new(&module, &mut vec![]);
+
+        assert!(symbols.is_void("log"));
+        assert!(!symbols.is_void("now"));
+        assert!(symbols.is_void("run"));
+        assert_eq!(symbols.result("value"), Some(Type::I32));
+        assert!(symbols.is_void("relay"));
+        assert!(symbols.is_void("last"));
+        assert!(!symbols.is_void("missing"));
+    }
+
+    #[test]
+    fn should_reject_unknown_types() {
+        let input = "struct P { x } enum E { A } func one(a: i16) { a }";
+        let module = parse(input, Source::Input).unwrap();
+        let mut errors = vec![];
+        Symbols::new(&module, &mut errors);
+
+        assert_eq!(
+            errors,
+            vec![CompileError::UnknownType {
+                name: "i16".to_string(),
+                declared: vec!["E".to_string(), "P".to_string()],
+                span: Span::new(40, 43),
+            }]
+        );
+        assert_eq!(
+            errors[0].help().as_deref(),
+            Some(
+                "the supported types are `i32`, `u32`, `i64`, `u64`, `f32`, `f64`, \
+                 `Int32Array`, `Uint16Array`, `Uint8Array`, `E` and `P`"
+            )
+        );
+    }
+
+    #[test]
+    fn should_reject_duplicate_functions() {
+        let input = "func one() { 1 } func one() { 2 }";
+        let module = parse(input, Source::Input).unwrap();
+        let mut errors = vec![];
+        Symbols::new(&module, &mut errors);
+
+        assert_eq!(
+            errors,
+            vec![CompileError::DuplicateDefinition {
+                name: "one".to_string(),
+                span: Span::new(22, 25),
+            }]
+        );
+    }
+
+    #[test]
+    fn should_reject_duplicate_parameters() {
+        let input = "func one(a, a) { a }";
+        let module = parse(input, Source::Input).unwrap();
+        let mut errors = vec![];
+        Symbols::new(&module, &mut errors);
+
+        assert_eq!(
+            errors,
+            vec![CompileError::DuplicateDefinition {
+                name: "a".to_string(),
+                span: Span::new(12, 13),
+            }]
+        );
+    }
+
+    #[test]
+    fn should_lay_out_struct_fields() {
+        let input = "struct S { a, b: f64, c: i64, d: S }";
+        let module = parse(input, Source::Input).unwrap();
+        let symbols = Symbols::new(&module, &mut vec![]);
+        let r#type = Type::Struct(symbols.type_name("S").unwrap());
+        let r#struct = symbols.r#struct(r#type).unwrap();
+
+        let offsets: Vec<_> = r#struct.fields.iter().map(|field| field.offset).collect();
+        assert_eq!(offsets, vec![0, 8, 16, 24]);
+        assert_eq!(r#struct.size, 28);
+        assert_eq!(r#struct.fields[3].r#type, r#type);
+    }
+
+    #[test]
+    fn should_keep_type_names_per_module() {
+        let first = Symbols::new(
+            &parse("struct A { x }", Source::Input).unwrap(),
+            &mut vec![],
+        );
+        let second = Symbols::new(&parse("enum B { X }", Source::Input).unwrap(), &mut vec![]);
+
+        assert_eq!(first.type_name("B"), None);
+        assert_eq!(second.type_name("A"), None);
+        assert_eq!(first.type_name("A"), second.type_name("B"));
+    }
+
+    #[test]
+    fn should_number_enum_variants() {
+        let input = "enum E { A, B = 10, C, D = 1 + 1, F = 11 } struct E { x }";
+        let module = parse(input, Source::Input).unwrap();
+        let mut errors = vec![];
+        let symbols = Symbols::new(&module, &mut errors);
+        let r#type = Type::Enum(symbols.type_name("E").unwrap());
+
+        let discriminants: Vec<_> = ["A", "B", "C", "D", "F"]
+            .iter()
+            .map(|variant| symbols.variant(r#type, variant))
+            .collect();
+
+        assert_eq!(
+            discriminants,
+            vec![Some(0), Some(10), Some(11), Some(2), Some(11)]
+        );
+        assert_eq!(
+            errors,
+            vec![
+                CompileError::DuplicateDiscriminant {
+                    value: 11,
+                    span: Span::new(34, 35),
+                },
+                CompileError::DuplicateDefinition {
+                    name: "E".to_string(),
+                    span: Span::new(50, 51),
+                },
+            ]
+        );
     }
 }
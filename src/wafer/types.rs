@@ -0,0 +1,462 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::wasm::ValueType;
+
+use super::ast::{BinaryOperator, Block, Expr, ExprKind, Identifier, UnaryOperator};
+
+// The width of an array element or memory access. Elements narrower than an
+// `i32` are zero-extended when read and truncated when written.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Element {
+    I32,
+    U16,
+    U8,
+}
+
+impl Element {
+    // The intrinsic arrays that address memory directly, one per width.
+    pub fn from_memory(array: &Expr) -> Option<Self> {
+        let ExprKind::Variable(identifier) = &array.kind else {
+            return None;
+        };
+
+        match identifier.name.as_str() {
+            "__mem" => Some(Element::I32),
+            "__mem16" => Some(Element::U16),
+            "__mem8" => Some(Element::U8),
+            _ => None,
+        }
+    }
+
+    pub fn size(self) -> i32 {
+        match self {
+            Element::I32 => 4,
+            Element::U16 => 2,
+            Element::U8 => 1,
+        }
+    }
+
+    pub fn array_name(self) -> &'static str {
+        match self {
+            Element::I32 => "Int32Array",
+            Element::U16 => "Uint16Array",
+            Element::U8 => "Uint8Array",
+        }
+    }
+}
+
+// Struct and enum types are identified by their name, which is interned so
+// that `Type` stays `Copy`. Their definitions are looked up in `Symbols`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct TypeName(usize);
+
+// The names of a module's structs and enums, which `Symbols` keeps. Only
+// declared types are interned, so looking up any other name fails.
+#[derive(Debug, Default)]
+pub struct TypeNames(Vec<String>);
+
+impl TypeNames {
+    pub const fn new() -> Self {
+        TypeNames(Vec::new())
+    }
+
+    pub fn intern(&mut self, name: &str) -> TypeName {
+        self.find(name).unwrap_or_else(|| {
+            self.0.push(name.to_string());
+            TypeName(self.0.len() - 1)
+        })
+    }
+
+    pub fn find(&self, name: &str) -> Option<TypeName> {
+        self.0
+            .iter()
+            .position(|interned| interned == name)
+            .map(TypeName)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    // Errors spell out their types, since only the table knows the names of
+    // structs and enums.
+    pub fn describe(&self, r#type: Type) -> String {
+        match r#type {
+            Type::Struct(name) | Type::Enum(name) => self.0[name.0].clone(),
+            _ => r#type.to_string(),
+        }
+    }
+}
+
+// Arrays are pointers to a length header followed by their elements. They are
+// `i32`s at runtime and interchangeable with them; their type only decides the
+// width of the elements when they are indexed. Structs are pointers too, and
+// enums are their variants' discriminants. Unsigned integers share their
+// representation with the signed ones, but pick the unsigned instructions for
+// division, comparisons and conversions.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Type {
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+    Array(Element),
+    Struct(TypeName),
+    Enum(TypeName),
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "i32" => Some(Type::I32),
+            "u32" => Some(Type::U32),
+            "i64" => Some(Type::I64),
+            "u64" => Some(Type::U64),
+            "f32" => Some(Type::F32),
+            "f64" => Some(Type::F64),
+            "Int32Array" => Some(Type::Array(Element::I32)),
+            "Uint16Array" => Some(Type::Array(Element::U16)),
+            "Uint8Array" => Some(Type::Array(Element::U8)),
+            _ => None,
+        }
+    }
+
+    pub fn value_type(self) -> ValueType {
+        match self {
+            Type::I32 | Type::U32 | Type::Array(_) | Type::Struct(_) | Type::Enum(_) => {
+                ValueType::I32
+            }
+            Type::I64 | Type::U64 => ValueType::I64,
+            Type::F32 => ValueType::F32,
+            Type::F64 => ValueType::F64,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Type::F32 | Type::F64)
+    }
+
+    pub fn is_unsigned(self) -> bool {
+        matches!(self, Type::U32 | Type::U64)
+    }
+
+    // Plain `i32`s can be indexed too, as the prelude does with raw pointers,
+    // and are treated as `Int32Array`s.
+    pub fn element(self) -> Element {
+        match self {
+            Type::Array(element) => element,
+            _ => Element::I32,
+        }
+    }
+
+    // Signed and unsigned integers need an explicit conversion, since the
+    // same bits mean different numbers. Plain `i32`s still stand in for
+    // pointers and discriminants, but a struct, enum or array is never mixed
+    // up with another one, nor an array with one of a different element type.
+    pub fn accepts(self, other: Type) -> bool {
+        match (self, other) {
+            (
+                Type::Struct(_) | Type::Enum(_) | Type::Array(_),
+                Type::Struct(_) | Type::Enum(_) | Type::Array(_),
+            ) => self == other,
+            _ => {
+                self.value_type() == other.value_type() && self.is_unsigned() == other.is_unsigned()
+            }
+        }
+    }
+}
+
+impl From<ValueType> for Type {
+    fn from(value_type: ValueType) -> Self {
+        match value_type {
+            ValueType::I32 => Type::I32,
+            ValueType::I64 => Type::I64,
+            ValueType::F32 => Type::F32,
+            ValueType::F64 => Type::F64,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::I32 => write!(f, "i32"),
+            Type::U32 => write!(f, "u32"),
+            Type::I64 => write!(f, "i64"),
+            Type::U64 => write!(f, "u64"),
+            Type::F32 => write!(f, "f32"),
+            Type::F64 => write!(f, "f64"),
+            Type::Array(element) => write!(f, "{}", element.array_name()),
+            // Their names are in the module's `TypeNames`.
+            Type::Struct(_) => write!(f, "struct"),
+            Type::Enum(_) => write!(f, "enum"),
+        }
+    }
+}
+
+pub trait Environment {
+    fn variable(&self, identifier: &Identifier) -> Option<Type>;
+
+    fn result(&self, function: &str) -> Option<Type>;
+
+    fn field(&self, r#type: Type, field: &str) -> Option<Type>;
+
+    fn type_names(&self) -> &TypeNames;
+}
+
+pub fn is_comparison(operator: BinaryOperator) -> bool {
+    matches!(
+        operator,
+        BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::LessThan
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::LessThanOrEqual
+            | BinaryOperator::GreaterThanOrEqual
+    )
+}
+
+pub fn is_logical(operator: BinaryOperator) -> bool {
+    matches!(
+        operator,
+        BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr
+    )
+}
+
+// Whether an expression always produces 0 or 1.
+pub fn is_boolean(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Binary { operator, .. } => is_comparison(*operator) || is_logical(*operator),
+        ExprKind::Unary { operator, .. } => *operator == UnaryOperator::Not,
+        _ => false,
+    }
+}
+
+// Operators that only exist for integers and are rejected on floats.
+pub fn is_integer_only(operator: BinaryOperator) -> bool {
+    matches!(
+        operator,
+        BinaryOperator::Remainder
+            | BinaryOperator::And
+            | BinaryOperator::Or
+            | BinaryOperator::Xor
+            | BinaryOperator::ShiftLeft
+            | BinaryOperator::ShiftRight
+            | BinaryOperator::ShiftRightUnsigned
+    )
+}
+
+// `!` tests its operand like a condition, so a literal operand gets its
+// default type; `-` and `~` produce a value of the operand's type.
+pub fn unary_operand(
+    operator: UnaryOperator,
+    operand: &Expr,
+    expected: Type,
+    env: &impl Environment,
+) -> Type {
+    infer(operand, env).unwrap_or_else(|| match operator {
+        UnaryOperator::Not => default_type(operand),
+        UnaryOperator::Negate | UnaryOperator::Complement => expected,
+    })
+}
+
+// Literals have no type of their own and take on whatever type their context
+// expects, so `None` means "fits any numeric type".
+pub fn infer(expr: &Expr, env: &impl Environment) -> Option<Type> {
+    match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Float(_) => None,
+        ExprKind::String(_) => Some(Type::Array(Element::U8)),
+        ExprKind::Index { .. } | ExprKind::IndexAssign { .. } => Some(Type::I32),
+        ExprKind::Variable(identifier) if identifier.name == "__heap_base" => Some(Type::I32),
+        ExprKind::Variable(identifier) => env.variable(identifier),
+        ExprKind::Assign { target, .. } => env.variable(target),
+        ExprKind::Field { object, field } | ExprKind::FieldAssign { object, field, .. } => {
+            infer(object, env).and_then(|r#type| env.field(r#type, &field.name))
+        }
+        ExprKind::Struct { name, .. } => env.type_names().find(&name.name).map(Type::Struct),
+        ExprKind::Array(_) => Some(Type::Array(Element::I32)),
+        ExprKind::Variant { r#enum, .. } => env.type_names().find(&r#enum.name).map(Type::Enum),
+        ExprKind::Match { arms, .. } => arms.iter().find_map(|arm| infer_block(&arm.body, env)),
+        ExprKind::Unary {
+            operator: UnaryOperator::Not,
+            ..
+        } => Some(Type::I32),
+        ExprKind::Unary { operand, .. } => infer(operand, env),
+        ExprKind::Binary {
+            operator,
+            left,
+            right,
+        } => {
+            if is_comparison(*operator) || is_logical(*operator) {
+                Some(Type::I32)
+            } else {
+                infer(left, env).or_else(|| infer(right, env))
+            }
+        }
+        ExprKind::Call { function, .. } => match function.name.as_str() {
+            "__trap" => None,
+            "__memorySize" | "__memoryGrow" | "len" => Some(Type::I32),
+            "concat" => Some(Type::Array(Element::U8)),
+            name => Type::from_name(name).or_else(|| env.result(name)),
+        },
+        ExprKind::If {
+            then_block,
+            else_block,
+            ..
+        } => infer_block(then_block, env).or_else(|| infer_block(else_block, env)),
+    }
+}
+
+pub fn infer_block(block: &Block, env: &impl Environment) -> Option<Type> {
+    block
+        .result
+        .as_deref()
+        .and_then(|result| infer(result, env))
+}
+
+// The type an expression gets when nothing around it says otherwise: `f64` if
+// it is built from float literals, `i32` for everything else.
+pub fn default_type(expr: &Expr) -> Type {
+    match &expr.kind {
+        ExprKind::Float(_) => Type::F64,
+        ExprKind::Binary {
+            operator,
+            left,
+            right,
+        } if !is_comparison(*operator) && !is_logical(*operator) => default_operands(left, right),
+        ExprKind::Unary {
+            operator: UnaryOperator::Negate,
+            operand,
+        } => default_type(operand),
+        ExprKind::If {
+            then_block,
+            else_block,
+            ..
+        } => match (&then_block.result, &else_block.result) {
+            (Some(left), Some(right)) => default_operands(left, right),
+            _ => Type::I32,
+        },
+        ExprKind::Match { arms, .. } => {
+            let float = arms
+                .iter()
+                .filter_map(|arm| arm.body.result.as_deref())
+                .any(|result| default_type(result) == Type::F64);
+
+            if float { Type::F64 } else { Type::I32 }
+        }
+        _ => Type::I32,
+    }
+}
+
+// A literal argument to a conversion is emitted directly as the target type,
+// unless it is a float literal being truncated to an integer.
+pub fn conversion_source(argument: &Expr, target: Type, env: &impl Environment) -> Type {
+    infer(argument, env).unwrap_or_else(|| match default_type(argument) {
+        literal if literal.is_float() && !target.is_float() => literal,
+        _ => target,
+    })
+}
+
+pub fn default_operands(left: &Expr, right: &Expr) -> Type {
+    if default_type(left) == Type::F64 || default_type(right) == Type::F64 {
+        Type::F64
+    } else {
+        Type::I32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::wafer::ast::Identifier;
+    use crate::wafer::{Source, parse};
+
+    use super::{Environment, Type, TypeNames, default_type, infer};
+
+    const NAMES: &TypeNames = &TypeNames::new();
+
+    struct Env(HashMap<&'static str, Type>);
+
+    impl Environment for Env {
+        fn variable(&self, identifier: &Identifier) -> Option<Type> {
+            self.0.get(identifier.name.as_str()).copied()
+        }
+
+        fn result(&self, function: &str) -> Option<Type> {
+            self.0.get(function).copied()
+        }
+
+        fn field(&self, _: Type, _: &str) -> Option<Type> {
+            None
+        }
+
+        fn type_names(&self) -> &TypeNames {
+            NAMES
+        }
+    }
+
+    fn infer_result(body: &str) -> Option<Type> {
+        let env = Env(HashMap::from([
+            ("small", Type::I32),
+            ("big", Type::I64),
+            ("now", Type::I64),
+            ("ratio", Type::F32),
+        ]));
+        let module = parse(&format!("func f() {{ {body} }}"), Source::Input).unwrap();
+        let result = module.functions[0].body.result.as_deref().unwrap();
+
+        infer(result, &env)
+    }
+
+    fn default_result(body: &str) -> Type {
+        let module = parse(&format!("func f() {{ {body} }}"), Source::Input).unwrap();
+
+        default_type(module.functions[0].body.result.as_deref().unwrap())
+    }
+
+    #[test]
+    fn should_leave_literals_untyped() {
+        assert_eq!(infer_result("1 + 2"), None);
+    }
+
+    #[test]
+    fn should_infer_from_either_operand() {
+        assert_eq!(infer_result("1 + big"), Some(Type::I64));
+        assert_eq!(infer_result("small * 2"), Some(Type::I32));
+    }
+
+    #[test]
+    fn should_infer_comparisons_as_i32() {
+        assert_eq!(infer_result("big < 2"), Some(Type::I32));
+    }
+
+    #[test]
+    fn should_infer_calls_and_conversions() {
+        assert_eq!(infer_result("now()"), Some(Type::I64));
+        assert_eq!(infer_result("i32(big)"), Some(Type::I32));
+        assert_eq!(infer_result("i64(small)"), Some(Type::I64));
+    }
+
+    #[test]
+    fn should_infer_floats_from_variables_not_literals() {
+        assert_eq!(infer_result("1.5 * ratio"), Some(Type::F32));
+        assert_eq!(infer_result("2.5 + 1"), None);
+        assert_eq!(infer_result("f64(small)"), Some(Type::F64));
+    }
+
+    #[test]
+    fn should_default_float_literals_to_f64() {
+        assert_eq!(default_result("2.5 + 1"), Type::F64);
+        assert_eq!(default_result("2e10"), Type::F64);
+        assert_eq!(default_result("1 + 2"), Type::I32);
+        assert_eq!(default_result("1.5 < 2.5"), Type::I32);
+    }
+
+    #[test]
+    fn should_infer_if_from_either_branch() {
+        assert_eq!(infer_result("if small { 1 } else { big }"), Some(Type::I64));
+    }
+}
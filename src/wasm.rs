@@ -35,6 +35,27 @@ impl WasmEncodable for i32 {
     }
 }
 
+impl WasmEncodable for i64 {
+    fn wasm_encode(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        leb128::write::signed(&mut buffer, *self).expect("failed to write LEB128");
+
+        buffer
+    }
+}
+
+impl WasmEncodable for f32 {
+    fn wasm_encode(&self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+}
+
+impl WasmEncodable for f64 {
+    fn wasm_encode(&self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+}
+
 impl<T> WasmEncodable for Vec<T>
 where
     T: WasmEncodable,
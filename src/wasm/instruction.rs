@@ -3,37 +3,153 @@ use super::{ValueType, WasmEncodable};
 #[derive(Debug, PartialEq)]
 pub enum Instruction {
     Unreachable,
+    Block(Option<ValueType>),
     Loop(Option<ValueType>),
     If(Option<ValueType>),
     Else,
     End,
     Break(usize),
+    BreakIf(usize),
+    BreakTable(Vec<usize>, usize),
+    Return,
     Call(usize),
     Drop,
     LocalGetI32(usize),
     LocalSetI32(usize),
     LocalTeeI32(usize),
+    LocalGetI64(usize),
+    LocalSetI64(usize),
+    LocalTeeI64(usize),
+    LocalGetF32(usize),
+    LocalSetF32(usize),
+    LocalTeeF32(usize),
+    LocalGetF64(usize),
+    LocalSetF64(usize),
+    LocalTeeF64(usize),
+    GlobalGet(usize),
+    GlobalSet(usize),
+    // Memory accesses take their alignment, as a power of two, and then their
+    // offset.
     LoadI32(usize, usize),
     StoreI32(usize, usize),
+    LoadI64(usize, usize),
+    StoreI64(usize, usize),
+    LoadF32(usize, usize),
+    StoreF32(usize, usize),
+    LoadF64(usize, usize),
+    StoreF64(usize, usize),
+    Load8UnsignedI32(usize, usize),
+    Load16UnsignedI32(usize, usize),
+    Store8I32(usize, usize),
+    Store16I32(usize, usize),
+    MemorySize,
+    MemoryGrow,
     ConstI32(i32),
+    ConstI64(i64),
+    ConstF32(f32),
+    ConstF64(f64),
+    EqualZeroI32,
     EqualI32,
     NotEqualI32,
     LessThanSignedI32,
+    LessThanUnsignedI32,
     GreaterThanSignedI32,
+    GreaterThanUnsignedI32,
     LessThanOrEqualSignedI32,
+    LessThanOrEqualUnsignedI32,
     GreaterThanOrEqualSignedI32,
+    GreaterThanOrEqualUnsignedI32,
     AddI32,
     SubtractI32,
     MultiplyI32,
     DivideSignedI32,
+    DivideUnsignedI32,
     AndI32,
     OrI32,
+    RemainderSignedI32,
+    RemainderUnsignedI32,
+    XorI32,
+    ShiftLeftI32,
+    ShiftRightSignedI32,
+    ShiftRightUnsignedI32,
+    EqualZeroI64,
+    EqualI64,
+    NotEqualI64,
+    LessThanSignedI64,
+    LessThanUnsignedI64,
+    GreaterThanSignedI64,
+    GreaterThanUnsignedI64,
+    LessThanOrEqualSignedI64,
+    LessThanOrEqualUnsignedI64,
+    GreaterThanOrEqualSignedI64,
+    GreaterThanOrEqualUnsignedI64,
+    AddI64,
+    SubtractI64,
+    MultiplyI64,
+    DivideSignedI64,
+    DivideUnsignedI64,
+    AndI64,
+    OrI64,
+    RemainderSignedI64,
+    RemainderUnsignedI64,
+    XorI64,
+    ShiftLeftI64,
+    ShiftRightSignedI64,
+    ShiftRightUnsignedI64,
+    EqualF32,
+    NotEqualF32,
+    LessThanF32,
+    GreaterThanF32,
+    LessThanOrEqualF32,
+    GreaterThanOrEqualF32,
+    EqualF64,
+    NotEqualF64,
+    LessThanF64,
+    GreaterThanF64,
+    LessThanOrEqualF64,
+    GreaterThanOrEqualF64,
+    AddF32,
+    SubtractF32,
+    MultiplyF32,
+    DivideF32,
+    NegateF32,
+    AddF64,
+    SubtractF64,
+    MultiplyF64,
+    DivideF64,
+    NegateF64,
+    WrapI64,
+    TruncateSignedF32ToI32,
+    TruncateUnsignedF32ToI32,
+    TruncateSignedF64ToI32,
+    TruncateUnsignedF64ToI32,
+    ExtendSignedI32,
+    ExtendUnsignedI32,
+    TruncateSignedF32ToI64,
+    TruncateUnsignedF32ToI64,
+    TruncateSignedF64ToI64,
+    TruncateUnsignedF64ToI64,
+    ConvertSignedI32ToF32,
+    ConvertUnsignedI32ToF32,
+    ConvertSignedI64ToF32,
+    ConvertUnsignedI64ToF32,
+    DemoteF64,
+    ConvertSignedI32ToF64,
+    ConvertUnsignedI32ToF64,
+    ConvertSignedI64ToF64,
+    ConvertUnsignedI64ToF64,
+    PromoteF32,
 }
 
 impl WasmEncodable for Instruction {
     fn wasm_encode(&self) -> Vec<u8> {
         match self {
             Instruction::Unreachable => vec![0x00],
+            Instruction::Block(r#type) => [
+                vec![0x02],
+                r#type.map(|t| t.wasm_encode()).unwrap_or(vec![0x40]),
+            ]
+            .concat(),
             Instruction::Loop(r#type) => [
                 vec![0x03],
                 r#type.map(|t| t.wasm_encode()).unwrap_or(vec![0x40]),
@@ -47,30 +163,160 @@ impl WasmEncodable for Instruction {
             Instruction::Else => vec![0x05],
             Instruction::End => vec![0x0b],
             Instruction::Break(index) => [vec![0x0c], index.wasm_encode()].concat(),
+            Instruction::BreakIf(index) => [vec![0x0d], index.wasm_encode()].concat(),
+            Instruction::BreakTable(labels, default) => {
+                [vec![0x0e], labels.wasm_encode(), default.wasm_encode()].concat()
+            }
+            Instruction::Return => vec![0x0f],
             Instruction::Call(index) => [vec![0x10], index.wasm_encode()].concat(),
             Instruction::Drop => vec![0x1a],
             Instruction::LocalGetI32(index) => [vec![0x20], index.wasm_encode()].concat(),
             Instruction::LocalSetI32(index) => [vec![0x21], index.wasm_encode()].concat(),
             Instruction::LocalTeeI32(index) => [vec![0x22], index.wasm_encode()].concat(),
-            Instruction::LoadI32(offset, align) => {
-                [vec![0x28], offset.wasm_encode(), align.wasm_encode()].concat()
+            Instruction::LocalGetI64(index) => [vec![0x20], index.wasm_encode()].concat(),
+            Instruction::LocalSetI64(index) => [vec![0x21], index.wasm_encode()].concat(),
+            Instruction::LocalTeeI64(index) => [vec![0x22], index.wasm_encode()].concat(),
+            Instruction::LocalGetF32(index) => [vec![0x20], index.wasm_encode()].concat(),
+            Instruction::LocalSetF32(index) => [vec![0x21], index.wasm_encode()].concat(),
+            Instruction::LocalTeeF32(index) => [vec![0x22], index.wasm_encode()].concat(),
+            Instruction::LocalGetF64(index) => [vec![0x20], index.wasm_encode()].concat(),
+            Instruction::LocalSetF64(index) => [vec![0x21], index.wasm_encode()].concat(),
+            Instruction::LocalTeeF64(index) => [vec![0x22], index.wasm_encode()].concat(),
+            Instruction::GlobalGet(index) => [vec![0x23], index.wasm_encode()].concat(),
+            Instruction::GlobalSet(index) => [vec![0x24], index.wasm_encode()].concat(),
+            Instruction::LoadI32(align, offset) => {
+                [vec![0x28], align.wasm_encode(), offset.wasm_encode()].concat()
+            }
+            Instruction::StoreI32(align, offset) => {
+                [vec![0x36], align.wasm_encode(), offset.wasm_encode()].concat()
+            }
+            Instruction::LoadI64(align, offset) => {
+                [vec![0x29], align.wasm_encode(), offset.wasm_encode()].concat()
+            }
+            Instruction::StoreI64(align, offset) => {
+                [vec![0x37], align.wasm_encode(), offset.wasm_encode()].concat()
+            }
+            Instruction::LoadF32(align, offset) => {
+                [vec![0x2a], align.wasm_encode(), offset.wasm_encode()].concat()
+            }
+            Instruction::StoreF32(align, offset) => {
+                [vec![0x38], align.wasm_encode(), offset.wasm_encode()].concat()
+            }
+            Instruction::LoadF64(align, offset) => {
+                [vec![0x2b], align.wasm_encode(), offset.wasm_encode()].concat()
+            }
+            Instruction::StoreF64(align, offset) => {
+                [vec![0x39], align.wasm_encode(), offset.wasm_encode()].concat()
             }
-            Instruction::StoreI32(offset, align) => {
-                [vec![0x36], offset.wasm_encode(), align.wasm_encode()].concat()
+            Instruction::Load8UnsignedI32(align, offset) => {
+                [vec![0x2d], align.wasm_encode(), offset.wasm_encode()].concat()
             }
+            Instruction::Load16UnsignedI32(align, offset) => {
+                [vec![0x2f], align.wasm_encode(), offset.wasm_encode()].concat()
+            }
+            Instruction::Store8I32(align, offset) => {
+                [vec![0x3a], align.wasm_encode(), offset.wasm_encode()].concat()
+            }
+            Instruction::Store16I32(align, offset) => {
+                [vec![0x3b], align.wasm_encode(), offset.wasm_encode()].concat()
+            }
+            Instruction::MemorySize => vec![0x3f, 0x00],
+            Instruction::MemoryGrow => vec![0x40, 0x00],
             Instruction::ConstI32(value) => [vec![0x41], value.wasm_encode()].concat(),
+            Instruction::ConstI64(value) => [vec![0x42], value.wasm_encode()].concat(),
+            Instruction::ConstF32(value) => [vec![0x43], value.wasm_encode()].concat(),
+            Instruction::ConstF64(value) => [vec![0x44], value.wasm_encode()].concat(),
+            Instruction::EqualZeroI32 => vec![0x45],
             Instruction::EqualI32 => vec![0x46],
             Instruction::NotEqualI32 => vec![0x47],
             Instruction::LessThanSignedI32 => vec![0x48],
+            Instruction::LessThanUnsignedI32 => vec![0x49],
             Instruction::GreaterThanSignedI32 => vec![0x4a],
+            Instruction::GreaterThanUnsignedI32 => vec![0x4b],
             Instruction::LessThanOrEqualSignedI32 => vec![0x4c],
+            Instruction::LessThanOrEqualUnsignedI32 => vec![0x4d],
             Instruction::GreaterThanOrEqualSignedI32 => vec![0x4e],
+            Instruction::GreaterThanOrEqualUnsignedI32 => vec![0x4f],
             Instruction::AddI32 => vec![0x6a],
             Instruction::SubtractI32 => vec![0x6b],
             Instruction::MultiplyI32 => vec![0x6c],
             Instruction::DivideSignedI32 => vec![0x6d],
+            Instruction::DivideUnsignedI32 => vec![0x6e],
             Instruction::AndI32 => vec![0x71],
             Instruction::OrI32 => vec![0x72],
+            Instruction::RemainderSignedI32 => vec![0x6f],
+            Instruction::RemainderUnsignedI32 => vec![0x70],
+            Instruction::XorI32 => vec![0x73],
+            Instruction::ShiftLeftI32 => vec![0x74],
+            Instruction::ShiftRightSignedI32 => vec![0x75],
+            Instruction::ShiftRightUnsignedI32 => vec![0x76],
+            Instruction::EqualZeroI64 => vec![0x50],
+            Instruction::EqualI64 => vec![0x51],
+            Instruction::NotEqualI64 => vec![0x52],
+            Instruction::LessThanSignedI64 => vec![0x53],
+            Instruction::LessThanUnsignedI64 => vec![0x54],
+            Instruction::GreaterThanSignedI64 => vec![0x55],
+            Instruction::GreaterThanUnsignedI64 => vec![0x56],
+            Instruction::LessThanOrEqualSignedI64 => vec![0x57],
+            Instruction::LessThanOrEqualUnsignedI64 => vec![0x58],
+            Instruction::GreaterThanOrEqualSignedI64 => vec![0x59],
+            Instruction::GreaterThanOrEqualUnsignedI64 => vec![0x5a],
+            Instruction::AddI64 => vec![0x7c],
+            Instruction::SubtractI64 => vec![0x7d],
+            Instruction::MultiplyI64 => vec![0x7e],
+            Instruction::DivideSignedI64 => vec![0x7f],
+            Instruction::DivideUnsignedI64 => vec![0x80],
+            Instruction::AndI64 => vec![0x83],
+            Instruction::OrI64 => vec![0x84],
+            Instruction::RemainderSignedI64 => vec![0x81],
+            Instruction::RemainderUnsignedI64 => vec![0x82],
+            Instruction::XorI64 => vec![0x85],
+            Instruction::ShiftLeftI64 => vec![0x86],
+            Instruction::ShiftRightSignedI64 => vec![0x87],
+            Instruction::ShiftRightUnsignedI64 => vec![0x88],
+            Instruction::EqualF32 => vec![0x5b],
+            Instruction::NotEqualF32 => vec![0x5c],
+            Instruction::LessThanF32 => vec![0x5d],
+            Instruction::GreaterThanF32 => vec![0x5e],
+            Instruction::LessThanOrEqualF32 => vec![0x5f],
+            Instruction::GreaterThanOrEqualF32 => vec![0x60],
+            Instruction::EqualF64 => vec![0x61],
+            Instruction::NotEqualF64 => vec![0x62],
+            Instruction::LessThanF64 => vec![0x63],
+            Instruction::GreaterThanF64 => vec![0x64],
+            Instruction::LessThanOrEqualF64 => vec![0x65],
+            Instruction::GreaterThanOrEqualF64 => vec![0x66],
+            Instruction::AddF32 => vec![0x92],
+            Instruction::SubtractF32 => vec![0x93],
+            Instruction::MultiplyF32 => vec![0x94],
+            Instruction::DivideF32 => vec![0x95],
+            Instruction::NegateF32 => vec![0x8c],
+            Instruction::AddF64 => vec![0xa0],
+            Instruction::SubtractF64 => vec![0xa1],
+            Instruction::MultiplyF64 => vec![0xa2],
+            Instruction::DivideF64 => vec![0xa3],
+            Instruction::NegateF64 => vec![0x9a],
+            Instruction::WrapI64 => vec![0xa7],
+            Instruction::TruncateSignedF32ToI32 => vec![0xa8],
+            Instruction::TruncateUnsignedF32ToI32 => vec![0xa9],
+            Instruction::TruncateSignedF64ToI32 => vec![0xaa],
+            Instruction::TruncateUnsignedF64ToI32 => vec![0xab],
+            Instruction::ExtendSignedI32 => vec![0xac],
+            Instruction::ExtendUnsignedI32 => vec![0xad],
+            Instruction::TruncateSignedF32ToI64 => vec![0xae],
+            Instruction::TruncateUnsignedF32ToI64 => vec![0xaf],
+            Instruction::TruncateSignedF64ToI64 => vec![0xb0],
+            Instruction::TruncateUnsignedF64ToI64 => vec![0xb1],
+            Instruction::ConvertSignedI32ToF32 => vec![0xb2],
+            Instruction::ConvertUnsignedI32ToF32 => vec![0xb3],
+            Instruction::ConvertSignedI64ToF32 => vec![0xb4],
+            Instruction::ConvertUnsignedI64ToF32 => vec![0xb5],
+            Instruction::DemoteF64 => vec![0xb6],
+            Instruction::ConvertSignedI32ToF64 => vec![0xb7],
+            Instruction::ConvertUnsignedI32ToF64 => vec![0xb8],
+            Instruction::ConvertSignedI64ToF64 => vec![0xb9],
+            Instruction::ConvertUnsignedI64ToF64 => vec![0xba],
+            Instruction::PromoteF32 => vec![0xbb],
         }
     }
 }
@@ -89,4 +335,49 @@ mod tests {
 
         assert_eq!(wasm, vec![65, 42]);
     }
+
+    #[test]
+    fn should_encode_const_i64() {
+        let instruction = Instruction::ConstI64(-5_000_000_000);
+
+        let wasm = instruction.wasm_encode();
+
+        assert_eq!(wasm, vec![0x42, 0x80, 0x9c, 0xe8, 0xaf, 0x6d]);
+    }
+
+    #[test]
+    fn should_encode_memory_grow() {
+        let instruction = Instruction::MemoryGrow;
+
+        let wasm = instruction.wasm_encode();
+
+        assert_eq!(wasm, vec![0x40, 0x00]);
+    }
+
+    #[test]
+    fn should_encode_break_table() {
+        let instruction = Instruction::BreakTable(vec![0, 2, 1], 3);
+
+        let wasm = instruction.wasm_encode();
+
+        assert_eq!(wasm, vec![0x0e, 0x03, 0x00, 0x02, 0x01, 0x03]);
+    }
+
+    #[test]
+    fn should_encode_byte_store() {
+        let instruction = Instruction::Store8I32(0, 4);
+
+        let wasm = instruction.wasm_encode();
+
+        assert_eq!(wasm, vec![0x3a, 0x00, 0x04]);
+    }
+
+    #[test]
+    fn should_encode_const_f64() {
+        let instruction = Instruction::ConstF64(1.5);
+
+        let wasm = instruction.wasm_encode();
+
+        assert_eq!(wasm, vec![0x44, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f]);
+    }
 }
@@ -1,6 +1,6 @@
 use super::section::{
-    CodeSection, DataSection, ExportSection, FunctionSection, ImportSection, MemorySection,
-    TypeSection,
+    CodeSection, DataSection, ExportSection, FunctionSection, GlobalSection, ImportSection,
+    MemorySection, TypeSection,
 };
 use super::{Instruction, ValueType, WasmEncodable};
 
@@ -10,6 +10,7 @@ pub struct Module {
     import: ImportSection,
     function: FunctionSection,
     memory: MemorySection,
+    global: GlobalSection,
     export: ExportSection,
     code: CodeSection,
     data: DataSection,
@@ -29,6 +30,7 @@ impl WasmEncodable for Module {
         result.extend(self.import.wasm_encode());
         result.extend(self.function.wasm_encode());
         result.extend(self.memory.wasm_encode());
+        result.extend(self.global.wasm_encode());
         result.extend(self.export.wasm_encode());
         result.extend(self.code.wasm_encode());
         result.extend(self.data.wasm_encode());
@@ -69,6 +71,14 @@ impl Module {
         self.export.add_memory(name, index);
     }
 
+    pub fn add_global(&mut self, r#type: ValueType, mutable: bool, init: Instruction) -> usize {
+        self.global.add(r#type, mutable, init)
+    }
+
+    pub fn export_global(&mut self, name: &str, index: usize) {
+        self.export.add_global(name, index);
+    }
+
     pub fn add_data_segment(&mut self, memory: usize, offset: usize, data: Vec<u8>) {
         self.data.add_segment(memory, offset, data);
     }
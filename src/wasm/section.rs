@@ -2,6 +2,7 @@ mod code;
 mod data;
 mod export;
 mod function;
+mod global;
 mod import;
 mod memory;
 mod r#type;
@@ -10,6 +11,7 @@ pub use code::CodeSection;
 pub use data::DataSection;
 pub use export::ExportSection;
 pub use function::FunctionSection;
+pub use global::GlobalSection;
 pub use import::ImportSection;
 pub use memory::MemorySection;
 pub use r#type::TypeSection;
@@ -5,6 +5,7 @@ use super::Section;
 pub enum ExportDescription {
     Function(usize),
     Memory(usize),
+    Global(usize),
 }
 
 impl WasmEncodable for ExportDescription {
@@ -12,6 +13,7 @@ impl WasmEncodable for ExportDescription {
         match self {
             ExportDescription::Function(index) => [vec![0x00], index.wasm_encode()].concat(),
             ExportDescription::Memory(index) => [vec![0x02], index.wasm_encode()].concat(),
+            ExportDescription::Global(index) => [vec![0x03], index.wasm_encode()].concat(),
         }
     }
 }
@@ -51,6 +53,10 @@ impl ExportSection {
         self.add(name, ExportDescription::Memory(index));
     }
 
+    pub fn add_global(&mut self, name: &str, index: usize) {
+        self.add(name, ExportDescription::Global(index));
+    }
+
     fn add(&mut self, name: &str, description: ExportDescription) {
         let export = Export {
             name: name.to_owned(),
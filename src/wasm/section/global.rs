@@ -0,0 +1,68 @@
+use crate::wasm::{Instruction, ValueType, WasmEncodable};
+
+use super::Section;
+
+pub struct Global {
+    r#type: ValueType,
+    mutable: bool,
+    init: Instruction,
+}
+
+impl WasmEncodable for Global {
+    fn wasm_encode(&self) -> Vec<u8> {
+        [
+            self.r#type.wasm_encode(),
+            vec![self.mutable.into()],
+            self.init.wasm_encode(),
+            Instruction::End.wasm_encode(),
+        ]
+        .concat()
+    }
+}
+
+#[derive(Default)]
+pub struct GlobalSection {
+    globals: Vec<Global>,
+}
+
+impl Section for GlobalSection {
+    type Contents = Vec<Global>;
+
+    const ID: u8 = 6;
+
+    fn contents(&self) -> &Self::Contents {
+        &self.globals
+    }
+}
+
+impl GlobalSection {
+    pub fn add(&mut self, r#type: ValueType, mutable: bool, init: Instruction) -> usize {
+        self.globals.push(Global {
+            r#type,
+            mutable,
+            init,
+        });
+        self.globals.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wasm::{Instruction, ValueType, WasmEncodable};
+
+    use super::GlobalSection;
+
+    #[test]
+    fn should_encode_global_section() {
+        let mut section = GlobalSection::default();
+        section.add(ValueType::I32, true, Instruction::ConstI32(42));
+        section.add(ValueType::I64, false, Instruction::ConstI64(-1));
+
+        let wasm = section.wasm_encode();
+
+        assert_eq!(
+            wasm,
+            vec![6, 11, 2, 0x7f, 1, 0x41, 42, 0x0b, 0x7e, 0, 0x42, 0x7f, 0x0b]
+        );
+    }
+}